@@ -9,10 +9,92 @@ defmodule SensorProcessor do
   # NIF functions - these will be replaced by the actual Rust implementations
   def process_visual_data(_raw_data), do: :erlang.nif_error(:nif_not_loaded)
   def process_audio_data(_raw_data), do: :erlang.nif_error(:nif_not_loaded)
+  def process_audio_data_with_params(_raw_data, _params), do: :erlang.nif_error(:nif_not_loaded)
   def process_radar_data(_raw_data), do: :erlang.nif_error(:nif_not_loaded)
   def process_lidar_data(_raw_data), do: :erlang.nif_error(:nif_not_loaded)
+  def process_lidar_data_with_params(_raw_data, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def lidar_stats(_raw_data, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def process_lidar_data_packed(_data, _scale, _origin), do: :erlang.nif_error(:nif_not_loaded)
   def calculate_flocking_forces(_drone_state, _neighbors, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def predict_trajectory(_state, _acceleration, _dt, _steps, _max_speed), do: :erlang.nif_error(:nif_not_loaded)
+  def required_thrust(_desired_accel, _gravity, _mass), do: :erlang.nif_error(:nif_not_loaded)
   def generate_mock_sensor_data(_drone_id, _noise_level), do: :erlang.nif_error(:nif_not_loaded)
+  def step_swarm(_states, _params, _step_params, _dt), do: :erlang.nif_error(:nif_not_loaded)
+  def world_new(), do: :erlang.nif_error(:nif_not_loaded)
+  def world_upsert(_world, _id, _state), do: :erlang.nif_error(:nif_not_loaded)
+  def world_remove(_world, _id), do: :erlang.nif_error(:nif_not_loaded)
+  def world_step(_world, _params, _dt), do: :erlang.nif_error(:nif_not_loaded)
+  def params_fingerprint(_params), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_flocking_forces_with_energy(_drone_state, _neighbors, _params, _energy, _base, _return_energy_threshold), do: :erlang.nif_error(:nif_not_loaded)
+  def validate_flocking_params(_params), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_avoidance_forces(_drone_state, _obstacles, _params, _avoid_types), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_boundary_forces(_drone_state, _bounds_min, _bounds_max, _boundary_margin), do: :erlang.nif_error(:nif_not_loaded)
+  def to_swarm_frame(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def per_neighbor_forces(_drone, _neighbors, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def suggest_neighbor_radius(_states, _target_neighbors), do: :erlang.nif_error(:nif_not_loaded)
+  def process_visual_data_with_params(_raw_data, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def safe_velocity(_state, _desired, _neighbors, _combined_radius, _horizon), do: :erlang.nif_error(:nif_not_loaded)
+  def generate_mock_sensor_data_with_params(_drone_id, _noise_level, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def serialize_sensor_data(_data), do: :erlang.nif_error(:nif_not_loaded)
+  def deserialize_sensor_data(_bytes), do: :erlang.nif_error(:nif_not_loaded)
+  def is_stale(_timestamp, _now, _max_age_ms), do: :erlang.nif_error(:nif_not_loaded)
+  def filter_fresh(_states, _now, _max_age_ms), do: :erlang.nif_error(:nif_not_loaded)
+  def simulate(_initial, _params, _obstacles, _bounds, _dt, _steps, _seed), do: :erlang.nif_error(:nif_not_loaded)
+  def autotune_weights(_sample, _base, _iterations), do: :erlang.nif_error(:nif_not_loaded)
+  def enclosing_sphere(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_combined_forces(_drone_state, _neighbors, _obstacles, _params, _avoidance_params), do: :erlang.nif_error(:nif_not_loaded)
+  def steering_smoother_new(_steering_smoothing), do: :erlang.nif_error(:nif_not_loaded)
+  def steering_smoother_apply(_smoother, _force), do: :erlang.nif_error(:nif_not_loaded)
+  def distance_matrix(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def angular_momentum(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def path_clear(_from, _to, _obstacles, _clearance), do: :erlang.nif_error(:nif_not_loaded)
+  def nearest_neighbor_distances(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def preset(_name), do: :erlang.nif_error(:nif_not_loaded)
+  def sensor_overlap(_a, _b, _sensor_range, _fov_deg), do: :erlang.nif_error(:nif_not_loaded)
+  def is_hovering(_state, _speed_eps, _accel, _accel_eps), do: :erlang.nif_error(:nif_not_loaded)
+  def quantize_vector(_v, _step), do: :erlang.nif_error(:nif_not_loaded)
+  def lidar_repulsion(_points, _avoidance_distance, _max_force), do: :erlang.nif_error(:nif_not_loaded)
+  def process_radar_data_with_params(_raw_data, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def state_history_new(_capacity), do: :erlang.nif_error(:nif_not_loaded)
+  def state_history_push(_history, _id, _position, _timestamp), do: :erlang.nif_error(:nif_not_loaded)
+  def heading_to(_from, _target), do: :erlang.nif_error(:nif_not_loaded)
+  def canonicalize_state(_state, _max_speed, _now), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_capsule_avoidance_forces(_drone_state, _capsules, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def swarm_hull_2d(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def radar_smoother_new(_alpha), do: :erlang.nif_error(:nif_not_loaded)
+  def radar_smoother_apply(_smoother, _range_readings), do: :erlang.nif_error(:nif_not_loaded)
+  def interpolate_state(_a, _b, _t_ms), do: :erlang.nif_error(:nif_not_loaded)
+  def sensor_health(_data), do: :erlang.nif_error(:nif_not_loaded)
+  def separation_from_pairs(_position, _neighbor_positions, _params), do: :erlang.nif_error(:nif_not_loaded)
+  def simplify_trajectory(_points, _epsilon), do: :erlang.nif_error(:nif_not_loaded)
+  def analyze_stability(_params), do: :erlang.nif_error(:nif_not_loaded)
+  def fragmentation_monitor_new(_window), do: :erlang.nif_error(:nif_not_loaded)
+  def fragmentation_monitor_push(_monitor, _components), do: :erlang.nif_error(:nif_not_loaded)
+  def curl_force(_position, _center, _axis, _strength), do: :erlang.nif_error(:nif_not_loaded)
+  def snapshot_size_bytes(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def calculate_avoidance_forces_with_hysteresis(_drone_state, _obstacles, _params, _hysteresis, _active), do: :erlang.nif_error(:nif_not_loaded)
+  def order_parameter(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def spawn_lattice(_count, _spacing, _origin, _layout), do: :erlang.nif_error(:nif_not_loaded)
+  def forward_clearance(_state, _obstacles, _max_range), do: :erlang.nif_error(:nif_not_loaded)
+  def weighted_centroid(_points, _weights), do: :erlang.nif_error(:nif_not_loaded)
+  def enforce_min_spacing(_states, _min_distance, _iterations), do: :erlang.nif_error(:nif_not_loaded)
+  def local_alignment_errors(_states, _neighbor_radius), do: :erlang.nif_error(:nif_not_loaded)
+  def merge_obstacles(_obstacles, _merge_distance), do: :erlang.nif_error(:nif_not_loaded)
+  def randomize_velocities(_states, _max_speed, _seed), do: :erlang.nif_error(:nif_not_loaded)
+  def dominant_frequency(_raw_data, _sample_rate), do: :erlang.nif_error(:nif_not_loaded)
+  def expansion_rate(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def intercept_point(_pursuer, _target, _pursuer_speed), do: :erlang.nif_error(:nif_not_loaded)
+  def min_jerk_trajectory(_start, _goal, _duration, _steps), do: :erlang.nif_error(:nif_not_loaded)
+  def shape_descriptor(_states), do: :erlang.nif_error(:nif_not_loaded)
+  def steering_smoother_new_with_warmup(_steering_smoothing, _warmup_frames), do: :erlang.nif_error(:nif_not_loaded)
+  def steering_smoother_is_warm(_smoother), do: :erlang.nif_error(:nif_not_loaded)
+  def radar_smoother_new_with_warmup(_alpha, _warmup_frames), do: :erlang.nif_error(:nif_not_loaded)
+  def radar_smoother_is_warm(_smoother), do: :erlang.nif_error(:nif_not_loaded)
+  def fragmentation_monitor_new_with_warmup(_window, _warmup_frames), do: :erlang.nif_error(:nif_not_loaded)
+  def fragmentation_monitor_is_warm(_monitor), do: :erlang.nif_error(:nif_not_loaded)
+  def sample_free_points(_center, _radius, _count, _obstacles, _seed), do: :erlang.nif_error(:nif_not_loaded)
+  def trail_repulsion(_history, _drone, _params, _trail_length), do: :erlang.nif_error(:nif_not_loaded)
+  def sensor_disagreement(_data), do: :erlang.nif_error(:nif_not_loaded)
 
   @doc """
   Processes visual spectrum data using Rust implementation.
@@ -47,6 +129,14 @@ defmodule SensorProcessor do
     process_lidar_data(point_cloud)
   end
 
+  @doc """
+  Processes a packed LiDAR point cloud using Rust implementation.
+  Each point is three little-endian 16-bit offsets from `origin`, scaled by `scale`.
+  """
+  def process_lidar_packed(data, scale, origin) when is_binary(data) do
+    process_lidar_data_packed(data, scale, origin)
+  end
+
   @doc """
   Calculates flocking forces using Rust implementation.
   Returns the calculated force vector for the given drone and neighbors.
@@ -65,7 +155,9 @@ defmodule SensorProcessor do
         vy: elem(drone_state.velocity, 1),
         vz: elem(drone_state.velocity, 2)
       },
-      timestamp: drone_state.last_update
+      timestamp: drone_state.last_update,
+      weight_overrides: Map.get(drone_state, :weight_overrides),
+      priority: Map.get(drone_state, :priority, 0)
     }
 
     rust_neighbors = Enum.map(neighbors, fn neighbor ->
@@ -81,7 +173,9 @@ defmodule SensorProcessor do
           vy: elem(neighbor.velocity, 1),
           vz: elem(neighbor.velocity, 2)
         },
-        timestamp: neighbor.last_update
+        timestamp: neighbor.last_update,
+        weight_overrides: Map.get(neighbor, :weight_overrides),
+        priority: Map.get(neighbor, :priority, 0)
       }
     end)
 
@@ -1,7 +1,14 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use serde::{Deserialize, Serialize};
-use rustler::NifStruct;
-use nalgebra::{Vector3, Point3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use rustler::{NifStruct, NifTaggedEnum, NifUnitEnum};
 use crate::{DroneState, DronePosition, DroneVelocity};
+use crate::sensors::Obstacle;
 
 #[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
 #[module = "FlockingParams"]
@@ -14,8 +21,43 @@ pub struct FlockingParams {
     pub alignment_weight: f64,
     pub cohesion_weight: f64,
     pub obstacle_avoidance_weight: f64,
+    pub separation_full_radius: bool,
+    pub topology: Topology,
+    pub separation_min_distance: f64,
+    pub min_speed_for_alignment: f64,
+    pub edge_speed_boost: f64,
+    pub enable_separation: bool,
+    pub enable_alignment: bool,
+    pub enable_cohesion: bool,
+    pub separation_aggregation: SeparationAggregation,
+    pub obstacles_as_neighbors: bool,
+    /// How the alignment rule turns neighbor headings into a force.
+    pub alignment_mode: AlignmentMode,
+    /// Extra separation per unit of closing speed, so neighbors approaching
+    /// fast repel harder than ones drifting apart. Zero disables it.
+    pub closing_speed_weight: f64,
+    /// Distance from the neighbors' center of mass inside which cohesion
+    /// stops pulling, so drones near the center don't jitter.
+    pub cohesion_dead_radius: f64,
+    /// Share of separation shifted onto the lower-priority drone of a pair:
+    /// it repels `1 + priority_yield` times as hard from a higher-priority
+    /// neighbor, which in turn repels only `1 - priority_yield` as hard.
+    pub priority_yield: f64,
+    /// Ignore neighbors hidden behind an obstacle for alignment and
+    /// cohesion, using the obstacles passed alongside the neighbors.
+    /// Separation still reacts to every nearby drone. Only entry points that
+    /// take obstacles (`calculate_boids_forces_with_obstacles`, `simulate`)
+    /// can occlude anything; `step_swarm` has no obstacles, so it is a no-op there.
+    pub line_of_sight: bool,
+    /// Strength of the repulsion from a drone's own recent positions, fed
+    /// from its `StateHistory`. Zero leaves trails ignored.
+    pub trail_weight: f64,
 }
 
+/// Names accepted by `FlockingParams::preset`.
+#[cfg(test)]
+pub const PRESET_NAMES: [&str; 3] = ["tight_formation", "loose_scout", "aggressive_avoid"];
+
 impl Default for FlockingParams {
     fn default() -> Self {
         Self {
@@ -27,8 +69,547 @@ impl Default for FlockingParams {
             alignment_weight: 1.0,
             cohesion_weight: 1.0,
             obstacle_avoidance_weight: 3.0,
+            separation_full_radius: false,
+            topology: Topology::Bounded,
+            separation_min_distance: 0.0,
+            min_speed_for_alignment: 0.0,
+            edge_speed_boost: 0.0,
+            enable_separation: true,
+            enable_alignment: true,
+            enable_cohesion: true,
+            separation_aggregation: SeparationAggregation::AverageNormalized,
+            obstacles_as_neighbors: false,
+            alignment_mode: AlignmentMode::SteerToAverage,
+            closing_speed_weight: 0.0,
+            cohesion_dead_radius: 0.0,
+            priority_yield: 0.0,
+            line_of_sight: false,
+            trail_weight: 0.0,
+        }
+    }
+}
+
+/// Per-drone replacement for the rule weights in `FlockingParams`, e.g. so
+/// scouts can cohere less than the core of the swarm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
+#[module = "FlockingWeights"]
+pub struct FlockingWeights {
+    pub separation_weight: f64,
+    pub alignment_weight: f64,
+    pub cohesion_weight: f64,
+    pub obstacle_avoidance_weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "AvoidanceParams"]
+pub struct AvoidanceParams {
+    /// Clearance beyond an obstacle's radius at which avoidance starts.
+    pub avoidance_distance: f64,
+    /// Strength of the sideways steer-around component relative to the
+    /// straight repulsion. Zero gives pure repulsion.
+    pub tangential_weight: f64,
+    /// When set, an obstacle within `critical_distance` of its surface
+    /// makes avoidance replace the flocking force instead of adding to it.
+    pub avoidance_priority: bool,
+    pub critical_distance: f64,
+    /// Physical radius of the drone, so clearances are measured from its
+    /// body rather than its center.
+    pub drone_radius: f64,
+    /// Ignore obstacles behind a moving drone (outside the hemisphere its
+    /// velocity points into). A hovering drone still avoids everything.
+    pub forward_only: bool,
+    /// Scale avoidance by `speed / max_speed` (capped at 1) when it is
+    /// combined with flocking, so fast drones brake harder than slow ones
+    /// at the same clearance. `max_speed` is the flocking one. A critical
+    /// encounter under `avoidance_priority` is never scaled down.
+    pub speed_scaled_avoidance: bool,
+}
+
+impl Default for AvoidanceParams {
+    fn default() -> Self {
+        Self {
+            avoidance_distance: 20.0,
+            tangential_weight: 0.0,
+            avoidance_priority: false,
+            critical_distance: 5.0,
+            drone_radius: 0.0,
+            forward_only: false,
+            speed_scaled_avoidance: false,
+        }
+    }
+}
+
+/// Per-tick choreography override for the batch step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifTaggedEnum)]
+pub enum SwarmMode {
+    /// Normal flocking.
+    Flock,
+    /// Strong separation across the full neighbor radius, no cohesion.
+    Disperse,
+    /// Boosted cohesion toward a fixed point.
+    Gather { point: (f64, f64, f64) },
+}
+
+// How strongly Disperse and Gather override the configured weights
+const SWARM_MODE_SCALE: f64 = 3.0;
+
+impl SwarmMode {
+    /// Flocking parameters with this mode's weight overrides applied.
+    pub fn apply(&self, params: &FlockingParams) -> FlockingParams {
+        match self {
+            SwarmMode::Flock | SwarmMode::Gather { .. } => params.clone(),
+            SwarmMode::Disperse => FlockingParams {
+                separation_weight: params.separation_weight * SWARM_MODE_SCALE,
+                cohesion_weight: 0.0,
+                separation_full_radius: true,
+                ..params.clone()
+            },
+        }
+    }
+}
+
+/// `(min, max)` corners of an axis-aligned box.
+pub type BoxCorners = ((f64, f64, f64), (f64, f64, f64));
+
+/// Options for the batch step that aren't part of the flocking rules.
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "StepParams"]
+pub struct StepParams {
+    pub mode: SwarmMode,
+    /// Hard walls. Drones bounce off them elastically.
+    pub bounce_bounds: Option<BoxCorners>,
+    pub integration: IntegrationParams,
+    /// Actuator resolution. When positive, each drone's final force is
+    /// snapped to multiples of this before integrating.
+    pub force_step: f64,
+    /// Path point the swarm centroid should track. Every drone gets the
+    /// same correction, scaled by `centroid_weight`, so the flock's
+    /// internal structure is left alone.
+    pub centroid_target: Option<(f64, f64, f64)>,
+    pub centroid_weight: f64,
+    /// Center of a vortex the swarm circulates around, counter-clockwise
+    /// about `curl_axis`, with strength `curl_weight`.
+    pub curl_center: Option<(f64, f64, f64)>,
+    pub curl_axis: (f64, f64, f64),
+    pub curl_weight: f64,
+    /// Update drones across the rayon thread pool. Each drone's forces are
+    /// still summed in the serial order and swarm-wide sums are taken
+    /// before the split, so the result is bit-identical to the serial path.
+    pub parallel: bool,
+}
+
+impl Default for StepParams {
+    fn default() -> Self {
+        Self {
+            mode: SwarmMode::Flock,
+            bounce_bounds: None,
+            integration: IntegrationParams::default(),
+            force_step: 0.0,
+            centroid_target: None,
+            centroid_weight: 1.0,
+            curl_center: None,
+            curl_axis: (0.0, 0.0, 1.0),
+            curl_weight: 1.0,
+            parallel: false,
+        }
+    }
+}
+
+/// Integrator used to advance position and velocity over one step.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, NifUnitEnum)]
+pub enum IntegrationMethod {
+    /// Semi-implicit Euler: the new velocity moves the drone for the whole step.
+    Euler,
+    /// Classic fourth-order Runge-Kutta with the acceleration held constant
+    /// over the step.
+    Rk4,
+}
+
+/// How the batch step advances each drone once its force is known.
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "IntegrationParams"]
+pub struct IntegrationParams {
+    pub method: IntegrationMethod,
+    /// Largest velocity change a single step may apply. Zero means
+    /// unlimited.
+    pub max_delta_v: f64,
+    /// Constant world acceleration added to the steering, in any direction.
+    /// It counts toward `max_delta_v` like any other acceleration. Zero by
+    /// default: drones hold altitude on their own.
+    pub gravity_vector: (f64, f64, f64),
+    /// Largest change in heading per step, in radians. The new velocity is
+    /// rotated back toward the old heading when the commanded turn is
+    /// sharper. Zero means unlimited.
+    pub max_turn_rate_rad: f64,
+}
+
+impl Default for IntegrationParams {
+    fn default() -> Self {
+        Self {
+            method: IntegrationMethod::Euler,
+            max_delta_v: 0.0,
+            gravity_vector: (0.0, 0.0, 0.0),
+            max_turn_rate_rad: 0.0,
+        }
+    }
+}
+
+impl IntegrationParams {
+    /// Integrates one step, first adding gravity and then scaling the total
+    /// acceleration down so that `acceleration * dt` stays within `max_delta_v`.
+    /// A turn limited by `max_turn_rate_rad` moves the drone along the
+    /// limited velocity for the whole step.
+    pub fn integrate(
+        &self,
+        position: &Vector3D,
+        velocity: &Vector3D,
+        acceleration: &Vector3D,
+        dt: f64,
+        max_speed: f64,
+    ) -> (Vector3D, Vector3D) {
+        let (gx, gy, gz) = self.gravity_vector;
+        let acceleration = acceleration.add(&Vector3D::new(gx, gy, gz));
+        let acceleration = if self.max_delta_v > 0.0 && dt > 0.0 {
+            acceleration.limit(self.max_delta_v / dt)
+        } else {
+            acceleration
+        };
+        let (new_position, new_velocity) = self.method.integrate(position, velocity, &acceleration, dt, max_speed);
+        
+        match limit_turn(velocity, &new_velocity, self.max_turn_rate_rad) {
+            Some(turned) => (position.add(&turned.multiply(dt)), turned),
+            None => (new_position, new_velocity),
+        }
+    }
+}
+
+// `new` rotated toward `old`'s heading so the two differ by at most
+// `max_angle`, keeping `new`'s speed. None when no limit applies.
+fn limit_turn(old: &Vector3D, new: &Vector3D, max_angle: f64) -> Option<Vector3D> {
+    if max_angle <= 0.0 || old.magnitude() == 0.0 || new.magnitude() == 0.0 {
+        return None;
+    }
+    let (from, to) = (old.normalize(), new.normalize());
+    let angle = from.dot(&to).clamp(-1.0, 1.0).acos();
+    if angle <= max_angle {
+        return None;
+    }
+    
+    // Rotate within the plane of both headings; a reversal has no unique
+    // plane, so turn horizontally like steer_around_direction does
+    let mut axis = from.cross(&to);
+    if axis.magnitude() < 1e-9 {
+        axis = from.cross(&Vector3D::new(0.0, 0.0, 1.0));
+        if axis.magnitude() < 1e-9 {
+            axis = from.cross(&Vector3D::new(1.0, 0.0, 0.0));
+        }
+    }
+    let axis = axis.normalize();
+    let heading = from.multiply(max_angle.cos()).add(&axis.cross(&from).multiply(max_angle.sin()));
+    Some(heading.multiply(new.magnitude()))
+}
+
+impl IntegrationMethod {
+    pub fn integrate(
+        self,
+        position: &Vector3D,
+        velocity: &Vector3D,
+        acceleration: &Vector3D,
+        dt: f64,
+        max_speed: f64,
+    ) -> (Vector3D, Vector3D) {
+        match self {
+            IntegrationMethod::Euler => integrate_motion(position, velocity, acceleration, dt, max_speed),
+            IntegrationMethod::Rk4 => integrate_motion_rk4(position, velocity, acceleration, dt, max_speed),
+        }
+    }
+}
+
+/// Elongated obstacle such as a wall edge or beam: every point within
+/// `radius` of the segment `start`-`end`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
+#[module = "Capsule"]
+pub struct Capsule {
+    pub start: (f64, f64, f64),
+    pub end: (f64, f64, f64),
+    pub radius: f64,
+}
+
+impl Capsule {
+    /// Point on the capsule's axis closest to `point`.
+    pub fn closest_point(&self, point: &Vector3D) -> Vector3D {
+        let (sx, sy, sz) = self.start;
+        let (ex, ey, ez) = self.end;
+        closest_point_on_segment(&Vector3D::new(sx, sy, sz), &Vector3D::new(ex, ey, ez), point)
+    }
+}
+
+/// How per-neighbor repulsion vectors are combined into the separation force.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, NifUnitEnum)]
+pub enum SeparationAggregation {
+    /// Average the contributions, then normalize to a unit direction.
+    AverageNormalized,
+    /// Sum the contributions and limit the result to `max_force`, so many
+    /// close neighbors push harder than one.
+    SumLimited,
+    /// Keep only the strongest single contribution, normalized like the average.
+    MaxContribution,
+}
+
+/// How neighbor headings become the alignment force.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, NifUnitEnum)]
+pub enum AlignmentMode {
+    /// Steer from the drone's own heading toward the average neighbor
+    /// heading.
+    SteerToAverage,
+    /// Use the average neighbor heading as the force itself, ignoring the
+    /// drone's own heading.
+    DirectionOnly,
+}
+
+/// World topology used for neighbor distances and position wrapping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifTaggedEnum)]
+pub enum Topology {
+    Bounded,
+    /// Wrap-around world spanning `-size/2..size/2` on each axis. An axis
+    /// with a size of zero does not wrap.
+    Toroidal { size: (f64, f64, f64) },
+}
+
+impl Topology {
+    /// Shortest vector from `from` to `to`, taking wrap-around into account.
+    pub fn offset(&self, from: &Vector3D, to: &Vector3D) -> Vector3D {
+        let diff = to.subtract(from);
+        match self {
+            Topology::Bounded => diff,
+            Topology::Toroidal { size } => Vector3D::new(
+                wrap_delta(diff.x, size.0),
+                wrap_delta(diff.y, size.1),
+                wrap_delta(diff.z, size.2),
+            ),
+        }
+    }
+    
+    pub fn distance(&self, a: &Vector3D, b: &Vector3D) -> f64 {
+        self.offset(a, b).magnitude()
+    }
+    
+    /// Maps a position back into the world after it crosses an edge.
+    pub fn wrap(&self, position: &Vector3D) -> Vector3D {
+        match self {
+            Topology::Bounded => position.clone(),
+            Topology::Toroidal { size } => Vector3D::new(
+                wrap_delta(position.x, size.0),
+                wrap_delta(position.y, size.1),
+                wrap_delta(position.z, size.2),
+            ),
+        }
+    }
+}
+
+impl Hash for Topology {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Topology::Bounded => 0u8.hash(state),
+            Topology::Toroidal { size } => {
+                1u8.hash(state);
+                size.0.to_bits().hash(state);
+                size.1.to_bits().hash(state);
+                size.2.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+// Folds a coordinate or delta into `-size/2..size/2`
+fn wrap_delta(value: f64, size: f64) -> f64 {
+    if size > 0.0 {
+        value - size * (value / size).round()
+    } else {
+        value
+    }
+}
+
+impl FlockingParams {
+    /// Radius within which neighbors contribute to separation.
+    pub fn effective_separation_radius(&self) -> f64 {
+        if self.separation_full_radius {
+            self.neighbor_radius
+        } else {
+            self.separation_radius
         }
     }
+    
+    /// These parameters with `drone`'s weight overrides applied, if it has
+    /// any.
+    pub fn for_drone(&self, drone: &DroneState) -> Cow<'_, FlockingParams> {
+        match &drone.weight_overrides {
+            Some(weights) => Cow::Owned(FlockingParams {
+                separation_weight: weights.separation_weight,
+                alignment_weight: weights.alignment_weight,
+                cohesion_weight: weights.cohesion_weight,
+                obstacle_avoidance_weight: weights.obstacle_avoidance_weight,
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+    
+    /// Content hash of the parameters, stable for a given build. Floats are
+    /// hashed by bit pattern, so any field change alters the result.
+    pub fn fingerprint(&self) -> u64 {
+        // Destructure so new fields can't be silently left out of the hash
+        let Self {
+            neighbor_radius,
+            separation_radius,
+            max_speed,
+            max_force,
+            separation_weight,
+            alignment_weight,
+            cohesion_weight,
+            obstacle_avoidance_weight,
+            separation_full_radius,
+            topology,
+            separation_min_distance,
+            min_speed_for_alignment,
+            edge_speed_boost,
+            enable_separation,
+            enable_alignment,
+            enable_cohesion,
+            separation_aggregation,
+            obstacles_as_neighbors,
+            alignment_mode,
+            closing_speed_weight,
+            cohesion_dead_radius,
+            priority_yield,
+            line_of_sight,
+            trail_weight,
+        } = self;
+        
+        let mut hasher = DefaultHasher::new();
+        for value in [
+            neighbor_radius,
+            separation_radius,
+            max_speed,
+            max_force,
+            separation_weight,
+            alignment_weight,
+            cohesion_weight,
+            obstacle_avoidance_weight,
+            separation_min_distance,
+            min_speed_for_alignment,
+            edge_speed_boost,
+            closing_speed_weight,
+            cohesion_dead_radius,
+            priority_yield,
+            trail_weight,
+        ] {
+            value.to_bits().hash(&mut hasher);
+        }
+        separation_full_radius.hash(&mut hasher);
+        topology.hash(&mut hasher);
+        enable_separation.hash(&mut hasher);
+        enable_alignment.hash(&mut hasher);
+        enable_cohesion.hash(&mut hasher);
+        separation_aggregation.hash(&mut hasher);
+        obstacles_as_neighbors.hash(&mut hasher);
+        alignment_mode.hash(&mut hasher);
+        line_of_sight.hash(&mut hasher);
+        hasher.finish()
+    }
+    
+    /// Curated starting points, looked up by name. Returns None for names
+    /// not in `PRESET_NAMES`.
+    pub fn preset(name: &str) -> Option<Self> {
+        let base = Self::default();
+        let params = match name {
+            // Close, well-aligned flight with strong pull to the group
+            "tight_formation" => Self {
+                neighbor_radius: 60.0,
+                separation_radius: 15.0,
+                max_speed: 30.0,
+                separation_weight: 1.5,
+                alignment_weight: 2.0,
+                cohesion_weight: 1.5,
+                ..base
+            },
+            // Wide spacing and weak cohesion for covering ground
+            "loose_scout" => Self {
+                neighbor_radius: 200.0,
+                separation_radius: 120.0,
+                max_speed: 60.0,
+                separation_weight: 2.5,
+                alignment_weight: 0.5,
+                cohesion_weight: 0.3,
+                ..base
+            },
+            // Obstacle avoidance dominates everything else
+            "aggressive_avoid" => Self {
+                max_force: 20.0,
+                separation_weight: 3.0,
+                obstacle_avoidance_weight: 8.0,
+                ..base
+            },
+            _ => return None,
+        };
+        Some(params)
+    }
+    
+    /// Speed cap for a drone at `position` given the swarm `centroid`.
+    /// Drones out at `neighbor_radius` or beyond get `max_speed` raised by
+    /// `edge_speed_boost` so they can catch up, drones at the centroid get
+    /// it lowered by the same share, and halfway out keeps `max_speed`.
+    /// A toroidal world has no meaningful centroid, so there every drone
+    /// keeps `max_speed`.
+    pub fn edge_max_speed(&self, centroid: &Vector3D, position: &Vector3D) -> f64 {
+        if self.neighbor_radius <= 0.0 || matches!(self.topology, Topology::Toroidal { .. }) {
+            return self.max_speed;
+        }
+        let edge = (position.distance_to(centroid) / self.neighbor_radius).clamp(0.0, 1.0);
+        self.max_speed * (1.0 + self.edge_speed_boost * (2.0 * edge - 1.0)).max(0.0)
+    }
+    
+    /// Returns human-readable warnings for settings that are accepted but
+    /// probably not what the caller intended.
+    pub fn validate(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        
+        if !self.separation_full_radius && self.separation_radius > self.neighbor_radius {
+            warnings.push(format!(
+                "separation_radius ({}) exceeds neighbor_radius ({}); separation only acts within neighbor_radius",
+                self.separation_radius, self.neighbor_radius
+            ));
+        }
+        
+        warnings
+    }
+    
+    /// Heuristic 0-1 risk that separation and cohesion trade off into a
+    /// vibration around the separation boundary.
+    ///
+    /// Separation switches on abruptly at the separation radius, so a drone
+    /// sitting there is alternately pushed out and pulled back in. The risk
+    /// grows with how hard the two rules accelerate the drone relative to
+    /// its top speed (`(separation_weight + cohesion_weight) * max_force /
+    /// max_speed`) and with how thin the cohesion-only shell between the
+    /// separation and neighbor radii is, and is damped by alignment.
+    /// Configurations scoring below about 0.2 are reliably stable: a shell
+    /// at least half the neighbor radius thick, combined rule gain under
+    /// one, and an alignment weight of at least one.
+    pub fn oscillation_risk(&self) -> f64 {
+        let separation = if self.enable_separation { self.separation_weight.max(0.0) } else { 0.0 };
+        let cohesion = if self.enable_cohesion { self.cohesion_weight.max(0.0) } else { 0.0 };
+        let alignment = if self.enable_alignment { self.alignment_weight.max(0.0) } else { 0.0 };
+        if separation == 0.0 || cohesion == 0.0 || self.neighbor_radius <= 0.0 {
+            return 0.0;
+        }
+        
+        let stiffness = (separation + cohesion) * self.max_force / self.max_speed.max(f64::EPSILON);
+        let shell = ((self.neighbor_radius - self.effective_separation_radius()) / self.neighbor_radius).clamp(0.0, 1.0);
+        let raw = stiffness * (1.0 - shell) / (1.0 + alignment);
+        
+        raw / (1.0 + raw)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -88,6 +669,34 @@ impl Vector3D {
         let dz = self.z - other.z;
         (dx * dx + dy * dy + dz * dz).sqrt()
     }
+    
+    pub fn dot(&self, other: &Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    
+    pub fn cross(&self, other: &Self) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+    
+    /// Mirror image about a surface with the given normal (need not be unit).
+    pub fn reflect(&self, normal: &Self) -> Self {
+        let n = normal.normalize();
+        self.subtract(&n.multiply(2.0 * self.dot(&n)))
+    }
+    
+    /// Rounds each component to the nearest multiple of `step`. A zero or
+    /// negative step leaves the vector unchanged.
+    pub fn quantize(&self, step: f64) -> Self {
+        if step <= 0.0 {
+            return self.clone();
+        }
+        let snap = |value: f64| (value / step).round() * step;
+        Self::new(snap(self.x), snap(self.y), snap(self.z))
+    }
 }
 
 impl From<DronePosition> for Vector3D {
@@ -102,245 +711,3435 @@ impl From<DroneVelocity> for Vector3D {
     }
 }
 
+impl From<Vector3D> for DronePosition {
+    fn from(vec: Vector3D) -> Self {
+        Self { x: vec.x, y: vec.y, z: vec.z }
+    }
+}
+
+impl From<Vector3D> for DroneVelocity {
+    fn from(vec: Vector3D) -> Self {
+        Self { vx: vec.x, vy: vec.y, vz: vec.z }
+    }
+}
+
 pub fn calculate_boids_forces(
     drone: &DroneState,
     neighbors: &[DroneState],
     params: &FlockingParams,
 ) -> Vector3D {
+    calculate_boids_forces_among(drone, neighbors, &[], params)
+}
+
+/// Like `calculate_boids_forces`, but with `obstacles_as_neighbors` set the
+/// obstacles also repel through the separation rule, and with
+/// `line_of_sight` set they hide the neighbors behind them.
+pub fn calculate_boids_forces_with_obstacles(
+    drone: &DroneState,
+    neighbors: &[DroneState],
+    obstacles: &[(f64, f64, f64, f64)],
+    params: &FlockingParams,
+) -> Vector3D {
+    calculate_boids_forces_among(drone, neighbors, obstacles, params)
+}
+
+fn calculate_boids_forces_among<'a>(
+    drone: &DroneState,
+    neighbors: impl IntoIterator<Item = &'a DroneState>,
+    obstacles: &[(f64, f64, f64, f64)],
+    params: &FlockingParams,
+) -> Vector3D {
+    let params = params.for_drone(drone);
+    let params = params.as_ref();
     let position = Vector3D::from(drone.position.clone());
     let velocity = Vector3D::from(drone.velocity.clone());
     
     // Find neighbors within range
     let nearby_neighbors: Vec<&DroneState> = neighbors
-        .iter()
+        .into_iter()
         .filter(|neighbor| {
             let neighbor_pos = Vector3D::from(neighbor.position.clone());
-            position.distance_to(&neighbor_pos) <= params.neighbor_radius
+            params.topology.distance(&position, &neighbor_pos) <= params.neighbor_radius
         })
         .collect();
     
-    // Calculate individual forces
-    let separation = calculate_separation(&position, &nearby_neighbors, params);
-    let alignment = calculate_alignment(&velocity, &nearby_neighbors, params);
-    let cohesion = calculate_cohesion(&position, &nearby_neighbors, params);
+    // Alignment and cohesion only follow neighbors the drone can see,
+    // sighting along the shortest path to them
+    let visible_neighbors: Vec<&DroneState> = if params.line_of_sight && !obstacles.is_empty() {
+        nearby_neighbors
+            .iter()
+            .copied()
+            .filter(|neighbor| {
+                let neighbor_pos = Vector3D::from(neighbor.position.clone());
+                let sighted = position.subtract(&params.topology.offset(&neighbor_pos, &position));
+                path_clear(&position, &sighted, obstacles, 0.0)
+            })
+            .collect()
+    } else {
+        nearby_neighbors.clone()
+    };
+    let obstacles = if params.obstacles_as_neighbors { obstacles } else { &[] };
     
-    // Combine forces with weights
-    let total_force = separation
-        .multiply(params.separation_weight)
-        .add(&alignment.multiply(params.alignment_weight))
-        .add(&cohesion.multiply(params.cohesion_weight));
+    // Calculate and blend only the enabled rules
+    let mut total_force = Vector3D::zero();
+    if params.enable_separation {
+        let separation = calculate_separation(&position, &velocity, drone.priority, &nearby_neighbors, obstacles, params);
+        total_force = total_force.add(&separation.multiply(params.separation_weight));
+    }
+    if params.enable_alignment {
+        let alignment = calculate_alignment(&velocity, &visible_neighbors, params);
+        total_force = total_force.add(&alignment.multiply(params.alignment_weight));
+    }
+    if params.enable_cohesion {
+        let cohesion = calculate_cohesion(&position, &visible_neighbors, params);
+        total_force = total_force.add(&cohesion.multiply(params.cohesion_weight));
+    }
     
     // Apply force limits
     total_force.limit(params.max_force)
 }
 
-fn calculate_separation(
-    position: &Vector3D,
-    neighbors: &[&DroneState],
+/// Steering force each neighbor would induce on its own, for visualizing
+/// force arrows. Because separation and cohesion are normalized, these do
+/// not sum to the combined force once there is more than one neighbor.
+pub fn per_neighbor_forces(
+    drone: &DroneState,
+    neighbors: &[DroneState],
     params: &FlockingParams,
-) -> Vector3D {
-    let mut separation_force = Vector3D::zero();
-    let mut count = 0;
+) -> Vec<(String, Vector3D)> {
+    neighbors
+        .iter()
+        .map(|neighbor| {
+            let force = calculate_boids_forces_among(drone, std::iter::once(neighbor), &[], params);
+            (neighbor.id.clone(), force)
+        })
+        .collect()
+}
+
+/// Advances the whole swarm by one tick. Each drone flocks with every other
+/// drone in `states`, then its motion is integrated over `dt`. Timestamps
+/// are left to the caller. No obstacles are involved, so `line_of_sight`
+/// never hides a neighbor here.
+pub fn step_swarm(
+    states: &[DroneState],
+    params: &FlockingParams,
+    step_params: &StepParams,
+    dt: f64,
+) -> Vec<DroneState> {
+    let flocking_params = step_params.mode.apply(params);
+    let bounce_box = step_params.bounce_bounds.map(BoundingBox::from_corners);
+    let centroid = swarm_centroid(states);
+    let centroid_correction = match step_params.centroid_target {
+        Some(target) => centroid_correction(states, &centroid, target, params)
+            .multiply(step_params.centroid_weight),
+        None => Vector3D::zero(),
+    };
     
-    for neighbor in neighbors {
-        let neighbor_pos = Vector3D::from(neighbor.position.clone());
-        let distance = position.distance_to(&neighbor_pos);
+    // Reads only the previous tick, so drones can be updated in any order
+    let step_drone = |(i, drone): (usize, &DroneState)| {
+        let position = Vector3D::from(drone.position.clone());
+        let velocity = Vector3D::from(drone.velocity.clone());
+        let others = states
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, other)| other);
+        let mut force = calculate_boids_forces_among(drone, others, &[], &flocking_params);
         
-        if distance > 0.0 && distance < params.separation_radius {
-            // Calculate vector pointing away from neighbor
-            let diff = position.subtract(&neighbor_pos);
-            let normalized_diff = diff.normalize();
-            
-            // Weight by inverse distance (closer = stronger repulsion)
-            let weighted_diff = normalized_diff.multiply(1.0 / distance);
-            separation_force = separation_force.add(&weighted_diff);
-            count += 1;
+        if let SwarmMode::Gather { point } = step_params.mode {
+            let target = Vector3D::new(point.0, point.1, point.2);
+            let gather = calculate_seek(&position, &velocity, &target, params);
+            force = force
+                .add(&gather.multiply(params.cohesion_weight * SWARM_MODE_SCALE))
+                .limit(params.max_force);
         }
-    }
-    
-    if count > 0 {
-        // Average the separation forces
-        separation_force = separation_force.multiply(1.0 / count as f64);
         
-        // Normalize to get desired direction
-        if separation_force.magnitude() > 0.0 {
-            separation_force.normalize()
-        } else {
-            Vector3D::zero()
+        if let Some(center) = step_params.curl_center {
+            let center = Vector3D::new(center.0, center.1, center.2);
+            let (ax, ay, az) = step_params.curl_axis;
+            force = force
+                .add(&curl_force(&position, &center, &Vector3D::new(ax, ay, az), step_params.curl_weight))
+                .limit(params.max_force);
+        }
+        
+        let force = force
+            .add(&centroid_correction)
+            .limit(params.max_force)
+            .quantize(step_params.force_step);
+        
+        let max_speed = params.edge_max_speed(&centroid, &position);
+        let (position, velocity) = integrate_motion_in_topology(
+            &position,
+            &velocity,
+            &force,
+            dt,
+            max_speed,
+            &params.topology,
+            &step_params.integration,
+        );
+        let (position, velocity) = match &bounce_box {
+            Some(bounds) => bounds.bounce(&position, &velocity),
+            None => (position, velocity),
+        };
+        
+        DroneState {
+            id: drone.id.clone(),
+            position: DronePosition::from(position),
+            velocity: DroneVelocity::from(velocity),
+            timestamp: drone.timestamp,
+            weight_overrides: drone.weight_overrides.clone(),
+            priority: drone.priority,
         }
+    };
+    
+    if step_params.parallel {
+        states.par_iter().enumerate().map(step_drone).collect()
     } else {
-        Vector3D::zero()
+        states.iter().enumerate().map(step_drone).collect()
     }
 }
 
-fn calculate_alignment(
-    velocity: &Vector3D,
-    neighbors: &[&DroneState],
-    _params: &FlockingParams,
-) -> Vector3D {
-    if neighbors.is_empty() {
+/// Circulating force of magnitude `strength` around the line through
+/// `center` along `axis`: perpendicular to both the axis and the drone's
+/// offset from it, counter-clockwise by the right-hand rule. Drones on the
+/// axis get nothing.
+pub fn curl_force(position: &Vector3D, center: &Vector3D, axis: &Vector3D, strength: f64) -> Vector3D {
+    let tangent = axis.normalize().cross(&position.subtract(center));
+    if tangent.magnitude() < 1e-9 {
         return Vector3D::zero();
     }
-    
-    // Calculate average velocity of neighbors
-    let mut avg_velocity = Vector3D::zero();
-    
-    for neighbor in neighbors {
-        let neighbor_vel = Vector3D::from(neighbor.velocity.clone());
-        avg_velocity = avg_velocity.add(&neighbor_vel);
-    }
-    
-    avg_velocity = avg_velocity.multiply(1.0 / neighbors.len() as f64);
-    
-    // Calculate desired velocity change
-    let desired_velocity = avg_velocity.normalize();
-    let current_velocity = velocity.normalize();
-    
-    desired_velocity.subtract(&current_velocity)
+    tangent.normalize().multiply(strength)
 }
 
-fn calculate_cohesion(
-    position: &Vector3D,
-    neighbors: &[&DroneState],
-    _params: &FlockingParams,
+// Steering for the swarm as a whole: the centroid seeks `target` at up to
+// `max_speed`, damped by the mean velocity so it settles instead of orbiting
+fn centroid_correction(
+    states: &[DroneState],
+    centroid: &Vector3D,
+    target: (f64, f64, f64),
+    params: &FlockingParams,
 ) -> Vector3D {
-    if neighbors.is_empty() {
+    if states.is_empty() {
         return Vector3D::zero();
     }
-    
-    // Calculate center of mass of neighbors
-    let mut center_of_mass = Vector3D::zero();
-    
-    for neighbor in neighbors {
-        let neighbor_pos = Vector3D::from(neighbor.position.clone());
-        center_of_mass = center_of_mass.add(&neighbor_pos);
+    let mean_velocity = states
+        .iter()
+        .fold(Vector3D::zero(), |sum, s| sum.add(&Vector3D::from(s.velocity.clone())))
+        .multiply(1.0 / states.len() as f64);
+    let desired = Vector3D::new(target.0, target.1, target.2)
+        .subtract(centroid)
+        .limit(params.max_speed);
+    desired.subtract(&mean_velocity).limit(params.max_force)
+}
+
+fn swarm_centroid(states: &[DroneState]) -> Vector3D {
+    if states.is_empty() {
+        return Vector3D::zero();
     }
+    let sum = states
+        .iter()
+        .fold(Vector3D::zero(), |sum, s| sum.add(&Vector3D::from(s.position.clone())));
+    sum.multiply(1.0 / states.len() as f64)
+}
+
+/// `count` stationary drones `drone_0`, `drone_1`, ... laid out from
+/// `origin` at `spacing`: `"line"` along x, `"grid"` as an x/y square and
+/// `"cube"` in x/y/z, each filled row by row. Unknown layouts give None.
+pub fn spawn_lattice(count: usize, spacing: f64, origin: (f64, f64, f64), layout: &str) -> Option<Vec<DroneState>> {
+    // Smallest side length whose `dims`-th power holds `count` drones
+    let side = |dims: u32| (1..).find(|n: &usize| n.pow(dims) >= count).unwrap_or(1);
+    let (per_row, per_layer) = match layout {
+        "line" => (count.max(1), count.max(1)),
+        "grid" => (side(2), count.max(1)),
+        "cube" => {
+            let n = side(3);
+            (n, n * n)
+        }
+        _ => return None,
+    };
     
-    center_of_mass = center_of_mass.multiply(1.0 / neighbors.len() as f64);
-    
-    // Calculate desired direction toward center of mass
-    let desired_direction = center_of_mass.subtract(position);
-    
-    if desired_direction.magnitude() > 0.0 {
-        desired_direction.normalize()
-    } else {
-        Vector3D::zero()
-    }
+    let lattice = (0..count)
+        .map(|i| {
+            let (column, row, layer) = (i % per_row, (i % per_layer) / per_row, i / per_layer);
+            DroneState {
+                id: format!("drone_{}", i),
+                position: DronePosition {
+                    x: origin.0 + column as f64 * spacing,
+                    y: origin.1 + row as f64 * spacing,
+                    z: origin.2 + layer as f64 * spacing,
+                },
+                velocity: DroneVelocity { vx: 0.0, vy: 0.0, vz: 0.0 },
+                timestamp: 0,
+                weight_overrides: None,
+                priority: 0,
+            }
+        })
+        .collect();
+    Some(lattice)
 }
 
-pub fn apply_boundary_forces(
-    position: &Vector3D,
-    velocity: &Vector3D,
-    world_bounds: (f64, f64, f64),
-    boundary_margin: f64,
-) -> Vector3D {
-    let (x_bound, y_bound, z_bound) = world_bounds;
-    let mut boundary_force = Vector3D::zero();
+/// Gives every drone a random velocity drawn uniformly from the ball of
+/// radius `max_speed`. The same `seed` always gives the same velocities.
+pub fn randomize_velocities(states: &[DroneState], max_speed: f64, seed: u64) -> Vec<DroneState> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    states
+        .iter()
+        .map(|state| {
+            // Rejection-sample the unit ball so directions are unbiased
+            let velocity = loop {
+                let candidate = Vector3D::new(
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                    rng.gen_range(-1.0..=1.0),
+                );
+                if candidate.magnitude() <= 1.0 {
+                    break candidate.multiply(max_speed);
+                }
+            };
+            DroneState { velocity: DroneVelocity::from(velocity), ..state.clone() }
+        })
+        .collect()
+}
+
+// Draws sample_free_points() makes per requested point before giving up,
+// so a ball that is mostly obstacle returns fewer points instead of hanging
+const FREE_SAMPLE_ATTEMPTS: usize = 20;
+
+/// Up to `count` points drawn uniformly from the ball of `radius` around
+/// `center` that lie outside every obstacle. Returns fewer when the ball
+/// is too crowded to find them quickly. The same `seed` always gives the
+/// same points.
+pub fn sample_free_points(
+    center: &Vector3D,
+    radius: f64,
+    count: usize,
+    obstacles: &[(f64, f64, f64, f64)],
+    seed: u64,
+) -> Vec<Vector3D> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points = Vec::with_capacity(count);
     
-    // X boundaries
-    if position.x < -x_bound/2.0 + boundary_margin {
-        boundary_force.x += ((-x_bound/2.0 + boundary_margin) - position.x) * 0.1;
-    } else if position.x > x_bound/2.0 - boundary_margin {
-        boundary_force.x -= (position.x - (x_bound/2.0 - boundary_margin)) * 0.1;
+    for _ in 0..count.saturating_mul(FREE_SAMPLE_ATTEMPTS) {
+        if points.len() == count {
+            break;
+        }
+        let offset = Vector3D::new(
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+            rng.gen_range(-1.0..=1.0),
+        );
+        if offset.magnitude() > 1.0 {
+            continue;
+        }
+        let point = center.add(&offset.multiply(radius));
+        let free = obstacles
+            .iter()
+            .all(|&(ox, oy, oz, r)| point.distance_to(&Vector3D::new(ox, oy, oz)) > r);
+        if free {
+            points.push(point);
+        }
     }
     
-    // Y boundaries
-    if position.y < -y_bound/2.0 + boundary_margin {
-        boundary_force.y += ((-y_bound/2.0 + boundary_margin) - position.y) * 0.1;
-    } else if position.y > y_bound/2.0 - boundary_margin {
-        boundary_force.y -= (position.y - (y_bound/2.0 - boundary_margin)) * 0.1;
-    }
+    points
+}
+
+// Distance from the world edge at which simulate() starts pushing back
+const SIMULATION_BOUNDARY_MARGIN: f64 = 50.0;
+
+// Size of the random nudge that separates drones sharing a position
+const TIE_BREAK_NUDGE: f64 = 1e-3;
+
+/// Runs `steps` ticks of flocking, obstacle avoidance and boundary forces
+/// inside the centered `world_bounds` box and returns the final states.
+/// `seed` drives the only randomness (splitting drones that sit exactly on
+/// top of each other), so identical inputs always give identical output.
+pub fn simulate(
+    initial: &[DroneState],
+    params: &FlockingParams,
+    obstacles: &[(f64, f64, f64, f64)],
+    world_bounds: (f64, f64, f64),
+    dt: f64,
+    steps: usize,
+    seed: u64,
+) -> Vec<DroneState> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let bounds = BoundingBox::centered(world_bounds);
+    let avoidance = AvoidanceParams::default();
+    let elapsed_ms = (dt * 1000.0).round() as u64;
+    let mut states = initial.to_vec();
     
-    // Z boundaries (ground and ceiling)
-    if position.z < boundary_margin {
-        boundary_force.z += (boundary_margin - position.z) * 0.2;
-    } else if position.z > z_bound - boundary_margin {
-        boundary_force.z -= (position.z - (z_bound - boundary_margin)) * 0.1;
+    for _ in 0..steps {
+        separate_coincident(&mut states, &mut rng);
+        
+        states = states
+            .iter()
+            .enumerate()
+            .map(|(i, drone)| {
+                let position = Vector3D::from(drone.position.clone());
+                let velocity = Vector3D::from(drone.velocity.clone());
+                let others = states
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other);
+                
+                let boundary = boundary_force_box(&position, &velocity, &bounds, SIMULATION_BOUNDARY_MARGIN);
+                let steering = calculate_boids_forces_among(drone, others, obstacles, params).add(&boundary);
+                let force = combine_with_avoidance(
+                    &steering,
+                    &position,
+                    &velocity,
+                    obstacles,
+                    &params.for_drone(drone),
+                    &avoidance,
+                );
+                
+                let (position, velocity) = integrate_motion_in_topology(
+                    &position,
+                    &velocity,
+                    &force,
+                    dt,
+                    params.max_speed,
+                    &params.topology,
+                    &IntegrationParams::default(),
+                );
+                let (position, velocity) = bounds.bounce(&position, &velocity);
+                
+                DroneState {
+                    id: drone.id.clone(),
+                    position: DronePosition::from(position),
+                    velocity: DroneVelocity::from(velocity),
+                    timestamp: drone.timestamp + elapsed_ms,
+                    weight_overrides: drone.weight_overrides.clone(),
+                    priority: drone.priority,
+                }
+            })
+            .collect();
     }
     
-    boundary_force
+    states
 }
 
-pub fn calculate_obstacle_avoidance(
+// Separation ignores zero-distance neighbors, so drones spawned on the same
+// spot would never split up without a nudge
+fn separate_coincident(states: &mut [DroneState], rng: &mut StdRng) {
+    for i in 1..states.len() {
+        let position = Vector3D::from(states[i].position.clone());
+        let coincident = states[..i]
+            .iter()
+            .any(|other| Vector3D::from(other.position.clone()).distance_to(&position) == 0.0);
+        if coincident {
+            let nudge = Vector3D::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            states[i].position = DronePosition::from(position.add(&nudge.multiply(TIE_BREAK_NUDGE)));
+        }
+    }
+}
+
+/// Positional relaxation: each of `iterations` passes moves every pair of
+/// drones closer than `min_distance` symmetrically apart along their
+/// separating line until they are exactly `min_distance` apart. Coincident
+/// pairs split along x. Velocities are left alone.
+pub fn enforce_min_spacing(states: &[DroneState], min_distance: f64, iterations: usize) -> Vec<DroneState> {
+    let mut positions: Vec<Vector3D> = states.iter().map(|s| Vector3D::from(s.position.clone())).collect();
+    
+    for _ in 0..iterations {
+        let mut moved = false;
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                let offset = positions[j].subtract(&positions[i]);
+                if offset.magnitude() >= min_distance {
+                    continue;
+                }
+                let direction = if offset.magnitude() > 0.0 {
+                    offset.normalize()
+                } else {
+                    Vector3D::new(1.0, 0.0, 0.0)
+                };
+                let midpoint = positions[i].add(&offset.multiply(0.5));
+                positions[i] = midpoint.subtract(&direction.multiply(min_distance / 2.0));
+                positions[j] = midpoint.add(&direction.multiply(min_distance / 2.0));
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+    
+    states
+        .iter()
+        .zip(positions)
+        .map(|(state, position)| DroneState { position: DronePosition::from(position), ..state.clone() })
+        .collect()
+}
+
+// Ticks and step size used to score a candidate weighting
+const STABILITY_STEPS: usize = 20;
+const STABILITY_DT: f64 = 0.1;
+
+/// Local search over the separation, alignment and cohesion weights that
+/// maximizes `stability_score` on `sample`. Each iteration tries nudging
+/// every weight up and down and keeps any improvement; the nudge shrinks
+/// when nothing helps.
+pub fn autotune_weights(sample: &[DroneState], base: &FlockingParams, iterations: usize) -> FlockingParams {
+    let mut best = base.clone();
+    let mut best_score = stability_score(sample, &best);
+    let mut step = 0.5;
+    
+    for _ in 0..iterations {
+        let mut improved = false;
+        
+        for weight in 0..3 {
+            let current = *weight_slot(&mut best, weight);
+            for candidate in [current + step, current - step, current * 1.5, current / 1.5] {
+                let mut trial = best.clone();
+                *weight_slot(&mut trial, weight) = candidate.max(0.0);
+                
+                let score = stability_score(sample, &trial);
+                if score > best_score {
+                    best = trial;
+                    best_score = score;
+                    improved = true;
+                }
+            }
+        }
+        
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    
+    best
+}
+
+fn weight_slot(params: &mut FlockingParams, index: usize) -> &mut f64 {
+    match index {
+        0 => &mut params.separation_weight,
+        1 => &mut params.alignment_weight,
+        _ => &mut params.cohesion_weight,
+    }
+}
+
+/// Scores how well `params` keep `sample` together over a short run. Zero
+/// is ideal; spread growth, near-collisions and running at the speed cap
+/// all subtract from it.
+pub fn stability_score(sample: &[DroneState], params: &FlockingParams) -> f64 {
+    if sample.len() < 2 {
+        return 0.0;
+    }
+    
+    let mut states = sample.to_vec();
+    for _ in 0..STABILITY_STEPS {
+        states = step_swarm(&states, params, &StepParams::default(), STABILITY_DT);
+    }
+    
+    let before = swarm_spread(sample);
+    let after = swarm_spread(&states);
+    let growth = (after - before).max(0.0) / before.max(f64::EPSILON);
+    
+    let collision_distance = params.separation_min_distance.max(1.0);
+    let mut collisions = 0;
+    for (i, a) in states.iter().enumerate() {
+        let a = Vector3D::from(a.position.clone());
+        for b in &states[i + 1..] {
+            if a.distance_to(&Vector3D::from(b.position.clone())) < collision_distance {
+                collisions += 1;
+            }
+        }
+    }
+    
+    let saturated = states
+        .iter()
+        .filter(|s| Vector3D::from(s.velocity.clone()).magnitude() >= params.max_speed * 0.99)
+        .count();
+    
+    -(growth + collisions as f64 + saturated as f64 / states.len() as f64)
+}
+
+fn swarm_spread(states: &[DroneState]) -> f64 {
+    if states.is_empty() {
+        return 0.0;
+    }
+    let centroid = swarm_centroid(states);
+    let total: f64 = states
+        .iter()
+        .map(|s| Vector3D::from(s.position.clone()).distance_to(&centroid))
+        .sum();
+    total / states.len() as f64
+}
+
+/// Neighbor radius at which drones would see `target_neighbors` neighbors on
+/// average. Picks the pairwise distance below which exactly
+/// `target_neighbors * n / 2` pairs fall.
+pub fn suggest_neighbor_radius(states: &[DroneState], target_neighbors: usize) -> f64 {
+    if states.len() < 2 || target_neighbors == 0 {
+        return 0.0;
+    }
+    
+    let positions: Vec<Vector3D> = states
+        .iter()
+        .map(|state| Vector3D::from(state.position.clone()))
+        .collect();
+    let mut distances = Vec::with_capacity(positions.len() * (positions.len() - 1) / 2);
+    for (i, a) in positions.iter().enumerate() {
+        for b in &positions[i + 1..] {
+            distances.push(a.distance_to(b));
+        }
+    }
+    distances.sort_by(f64::total_cmp);
+    
+    let pairs_needed = (target_neighbors * states.len()).div_ceil(2);
+    distances[pairs_needed.clamp(1, distances.len()) - 1]
+}
+
+/// Boids forces for a drone with a battery level. Once `energy` drops below
+/// `return_energy_threshold` and a base is known, flocking is abandoned in
+/// favour of a full-strength seek toward the base.
+pub fn calculate_forces_with_energy(
+    drone: &DroneState,
+    neighbors: &[DroneState],
+    params: &FlockingParams,
+    energy: f64,
+    base: Option<&Vector3D>,
+    return_energy_threshold: f64,
+) -> Vector3D {
+    match base {
+        Some(base) if energy < return_energy_threshold => {
+            let position = Vector3D::from(drone.position.clone());
+            let velocity = Vector3D::from(drone.velocity.clone());
+            calculate_seek(&position, &velocity, base, params)
+        }
+        _ => calculate_boids_forces(drone, neighbors, params),
+    }
+}
+
+/// Steering force toward `target` at full speed, limited to `max_force`.
+pub fn calculate_seek(
     position: &Vector3D,
     velocity: &Vector3D,
-    obstacles: &[(f64, f64, f64, f64)], // (x, y, z, radius)
-    avoidance_distance: f64,
+    target: &Vector3D,
+    params: &FlockingParams,
+) -> Vector3D {
+    let desired_velocity = target.subtract(position).normalize().multiply(params.max_speed);
+    desired_velocity.subtract(velocity).limit(params.max_force)
+}
+
+// Obstacles act as fixed neighbors whose distance is measured to their
+// surface, so larger obstacles repel from further away
+fn calculate_separation(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    priority: i32,
+    neighbors: &[&DroneState],
+    obstacles: &[(f64, f64, f64, f64)],
+    params: &FlockingParams,
 ) -> Vector3D {
-    let mut avoidance_force = Vector3D::zero();
+    // Extra repulsion from something at `diff` moving at `other`, growing
+    // with the speed at which the gap shrinks
+    let closing_boost = |diff: &Vector3D, other: &Vector3D| {
+        let closing = other.subtract(velocity).dot(&diff.normalize());
+        1.0 + params.closing_speed_weight * closing.max(0.0)
+    };
     
-    for &(ox, oy, oz, radius) in obstacles {
-        let obstacle_pos = Vector3D::new(ox, oy, oz);
-        let distance = position.distance_to(&obstacle_pos);
-        let danger_distance = radius + avoidance_distance;
-        
-        if distance < danger_distance && distance > 0.0 {
-            // Calculate avoidance vector
-            let avoidance_dir = position.subtract(&obstacle_pos).normalize();
-            
-            // Strength inversely proportional to distance
-            let strength = (danger_distance - distance) / danger_distance;
-            let weighted_avoidance = avoidance_dir.multiply(strength * 2.0);
+    let drone_offsets = neighbors.iter().map(|neighbor| {
+        let neighbor_pos = Vector3D::from(neighbor.position.clone());
+        let diff = params.topology.offset(&neighbor_pos, position);
+        let distance = diff.magnitude();
+        let right_of_way = match neighbor.priority.cmp(&priority) {
+            std::cmp::Ordering::Greater => 1.0 + params.priority_yield,
+            std::cmp::Ordering::Less => (1.0 - params.priority_yield).max(0.0),
+            std::cmp::Ordering::Equal => 1.0,
+        };
+        let boost = closing_boost(&diff, &Vector3D::from(neighbor.velocity.clone())) * right_of_way;
+        (diff, distance, boost)
+    });
+    let obstacle_offsets = obstacles.iter().map(|&(ox, oy, oz, radius)| {
+        let diff = params.topology.offset(&Vector3D::new(ox, oy, oz), position);
+        let distance = (diff.magnitude() - radius).max(f64::EPSILON);
+        let boost = closing_boost(&diff, &Vector3D::zero());
+        (diff, distance, boost)
+    });
+    
+    separation_from_offsets(drone_offsets.chain(obstacle_offsets), params)
+}
+
+/// Separation from neighbor positions paired with distances the caller has
+/// already measured, e.g. by its own broadphase. The distances are trusted
+/// as given; only the directions are computed here.
+pub fn separation_from_pairs(
+    position: &Vector3D,
+    neighbors: &[(Vector3D, f64)],
+    params: &FlockingParams,
+) -> Vector3D {
+    let offsets = neighbors
+        .iter()
+        .map(|(neighbor_pos, distance)| (params.topology.offset(neighbor_pos, position), *distance, 1.0));
+    separation_from_offsets(offsets, params)
+}
+
+/// Weak separation from the drone's own trail, to keep it from doubling
+/// back: the trail positions repel like neighbors and the result is scaled
+/// by `trail_weight`. Points within the separation radius count; the
+/// drone's current position, if it is in the trail, is ignored.
+pub fn trail_repulsion(position: &Vector3D, trail: &[Vector3D], params: &FlockingParams) -> Vector3D {
+    let offsets = trail.iter().map(|point| {
+        let diff = params.topology.offset(point, position);
+        let distance = diff.magnitude();
+        (diff, distance, 1.0)
+    });
+    separation_from_offsets(offsets, params).multiply(params.trail_weight)
+}
+
+// Aggregates (offset away from neighbor, distance, strength multiplier)
+// triples per `separation_aggregation`
+fn separation_from_offsets(
+    offsets: impl Iterator<Item = (Vector3D, f64, f64)>,
+    params: &FlockingParams,
+) -> Vector3D {
+    let mut separation_force = Vector3D::zero();
+    let mut strongest = Vector3D::zero();
+    let mut strongest_boost = 0.0;
+    let mut count = 0;
+    let mut total_boost = 0.0;
+    let separation_radius = params.effective_separation_radius();
+    
+    for (diff, distance, boost) in offsets {
+        if diff.magnitude() > 0.0 && distance < separation_radius {
+            // Calculate vector pointing away from neighbor
+            let normalized_diff = diff.normalize();
             
-            avoidance_force = avoidance_force.add(&weighted_avoidance);
+            // Weight by inverse distance (closer = stronger repulsion), with
+            // the distance floored so near-contact neighbors stay finite
+            let weighted_diff = normalized_diff.multiply(boost / distance.max(params.separation_min_distance));
+            if weighted_diff.magnitude() > strongest.magnitude() {
+                strongest = weighted_diff.clone();
+                strongest_boost = boost;
+            }
+            separation_force = separation_force.add(&weighted_diff);
+            total_boost += boost;
+            count += 1;
         }
     }
     
-    avoidance_force
+    match params.separation_aggregation {
+        SeparationAggregation::SumLimited => return separation_force.limit(params.max_force),
+        SeparationAggregation::MaxContribution => {
+            // Same unit scale as the average, so switching modes keeps the weight meaningful
+            return if strongest.magnitude() > 0.0 {
+                strongest.normalize().multiply(strongest_boost)
+            } else {
+                Vector3D::zero()
+            };
+        }
+        SeparationAggregation::AverageNormalized => {}
+    }
+    
+    if count > 0 {
+        // Average the separation forces
+        separation_force = separation_force.multiply(1.0 / count as f64);
+        
+        // Normalize to get desired direction, keeping the average boost
+        if separation_force.magnitude() > 0.0 {
+            separation_force.normalize().multiply(total_boost / count as f64)
+        } else {
+            Vector3D::zero()
+        }
+    } else {
+        Vector3D::zero()
+    }
 }
 
-pub fn integrate_motion(
-    position: &Vector3D,
+fn calculate_alignment(
     velocity: &Vector3D,
-    acceleration: &Vector3D,
-    dt: f64,
-    max_speed: f64,
-) -> (Vector3D, Vector3D) {
-    // Update velocity with acceleration
-    let new_velocity = velocity.add(&acceleration.multiply(dt));
-    let limited_velocity = new_velocity.limit(max_speed);
+    neighbors: &[&DroneState],
+    params: &FlockingParams,
+) -> Vector3D {
+    // Calculate average velocity of neighbors, ignoring near-stationary ones
+    // whose heading is mostly noise
+    let mut avg_velocity = Vector3D::zero();
+    let mut count = 0;
     
-    // Update position with velocity
-    let new_position = position.add(&limited_velocity.multiply(dt));
+    for neighbor in neighbors {
+        let neighbor_vel = Vector3D::from(neighbor.velocity.clone());
+        if neighbor_vel.magnitude() < params.min_speed_for_alignment {
+            continue;
+        }
+        avg_velocity = avg_velocity.add(&neighbor_vel);
+        count += 1;
+    }
     
-    (new_position, limited_velocity)
+    if count == 0 {
+        return Vector3D::zero();
+    }
+    
+    avg_velocity = avg_velocity.multiply(1.0 / count as f64);
+    
+    // Calculate desired velocity change
+    let desired_velocity = avg_velocity.normalize();
+    match params.alignment_mode {
+        AlignmentMode::SteerToAverage => desired_velocity.subtract(&velocity.normalize()),
+        AlignmentMode::DirectionOnly => desired_velocity,
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn calculate_cohesion(
+    position: &Vector3D,
+    neighbors: &[&DroneState],
+    params: &FlockingParams,
+) -> Vector3D {
+    if neighbors.is_empty() {
+        return Vector3D::zero();
+    }
     
-    #[test]
-    fn test_vector3d_operations() {
-        let v1 = Vector3D::new(1.0, 2.0, 3.0);
-        let v2 = Vector3D::new(4.0, 5.0, 6.0);
-        
-        let sum = v1.add(&v2);
-        assert_eq!(sum.x, 5.0);
-        assert_eq!(sum.y, 7.0);
-        assert_eq!(sum.z, 9.0);
-        
-        let magnitude = v1.magnitude();
-        assert!((magnitude - 3.7416573868).abs() < 1e-6);
+    // Calculate center of mass of neighbors, relative to this drone so
+    // wrap-around offsets average correctly
+    let mut center_offset = Vector3D::zero();
+    
+    for neighbor in neighbors {
+        let neighbor_pos = Vector3D::from(neighbor.position.clone());
+        center_offset = center_offset.add(&params.topology.offset(position, &neighbor_pos));
     }
     
-    #[test]
-    fn test_separation_force() {
-        let position = Vector3D::new(0.0, 0.0, 0.0);
-        let neighbor = DroneState {
-            id: "test".to_string(),
-            position: DronePosition { x: 10.0, y: 0.0, z: 0.0 },
-            velocity: DroneVelocity { vx: 0.0, vy: 0.0, vz: 0.0 },
-            timestamp: 0,
-        };
+    // Calculate desired direction toward center of mass
+    let desired_direction = center_offset.multiply(1.0 / neighbors.len() as f64);
+    
+    if desired_direction.magnitude() > params.cohesion_dead_radius.max(0.0) {
+        desired_direction.normalize()
+    } else {
+        Vector3D::zero()
+    }
+}
+
+/// Symmetric matrix of pairwise drone distances with a zero diagonal. Each
+/// pair is measured once and mirrored.
+pub fn distance_matrix(states: &[DroneState]) -> Vec<Vec<f64>> {
+    let positions: Vec<Vector3D> = states
+        .iter()
+        .map(|s| Vector3D::from(s.position.clone()))
+        .collect();
+    let mut matrix = vec![vec![0.0; positions.len()]; positions.len()];
+    
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let distance = positions[i].distance_to(&positions[j]);
+            matrix[i][j] = distance;
+            matrix[j][i] = distance;
+        }
+    }
+    
+    matrix
+}
+
+/// Distance from each drone to its closest other drone, in input order.
+/// Empty when there are fewer than two drones.
+pub fn nearest_neighbor_distances(states: &[DroneState]) -> Vec<f64> {
+    if states.len() < 2 {
+        return Vec::new();
+    }
+    
+    let positions: Vec<Vector3D> = states
+        .iter()
+        .map(|s| Vector3D::from(s.position.clone()))
+        .collect();
+    let grid = SpatialGrid::new(&positions);
+    
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| grid.nearest_other(&positions, i, position))
+        .collect()
+}
+
+/// Uniform hash grid over drone positions, sized so cells hold about one
+/// drone on average.
+struct SpatialGrid {
+    cell_size: f64,
+    max_ring: i64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    fn new(positions: &[Vector3D]) -> Self {
+        let mut min = positions[0].clone();
+        let mut max = positions[0].clone();
+        for p in positions {
+            min = Vector3D::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3D::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max.subtract(&min);
+        let largest = extent.x.max(extent.y).max(extent.z);
+        let cell_size = (largest / (positions.len() as f64).cbrt()).max(1e-6);
         
-        let neighbors = vec![&neighbor];
-        let params = FlockingParams::default();
+        let mut grid = Self {
+            cell_size,
+            max_ring: (largest / cell_size).ceil() as i64 + 1,
+            cells: HashMap::new(),
+        };
+        for (i, p) in positions.iter().enumerate() {
+            grid.cells.entry(grid.key(p)).or_default().push(i);
+        }
+        grid
+    }
+    
+    fn key(&self, p: &Vector3D) -> (i64, i64, i64) {
+        (
+            (p.x / self.cell_size).floor() as i64,
+            (p.y / self.cell_size).floor() as i64,
+            (p.z / self.cell_size).floor() as i64,
+        )
+    }
+    
+    // Searches rings of cells outward from the drone's own cell. Anything in
+    // ring r + 1 is at least r cells away, so the search stops once the best
+    // distance found is within that bound.
+    fn nearest_other(&self, positions: &[Vector3D], index: usize, position: &Vector3D) -> f64 {
+        let (cx, cy, cz) = self.key(position);
+        let mut best = f64::INFINITY;
         
-        let force = calculate_separation(&position, &neighbors, &params);
+        for ring in 0..=self.max_ring {
+            for dx in -ring..=ring {
+                for dy in -ring..=ring {
+                    for dz in -ring..=ring {
+                        if dx.abs().max(dy.abs()).max(dz.abs()) != ring {
+                            continue;
+                        }
+                        let Some(members) = self.cells.get(&(cx + dx, cy + dy, cz + dz)) else {
+                            continue;
+                        };
+                        for &j in members {
+                            if j != index {
+                                best = best.min(position.distance_to(&positions[j]));
+                            }
+                        }
+                    }
+                }
+            }
+            if best <= ring as f64 * self.cell_size {
+                break;
+            }
+        }
         
-        // Should point away from neighbor (negative x direction)
-        assert!(force.x < 0.0);
-        assert!(force.magnitude() > 0.0);
+        best
+    }
+}
+
+// Samples used to estimate the overlap of two sensor cones
+const OVERLAP_SAMPLES: usize = 4096;
+
+/// Approximate fraction of drone `a`'s forward sensor cone (apex at the
+/// drone, axis along its velocity, full angle `fov_deg`, length
+/// `sensor_range`) that also lies in `b`'s. Both cones have the same
+/// volume, so the fraction is the same from either side. Estimated from a
+/// fixed set of samples, so it is deterministic.
+pub fn sensor_overlap(a: &DroneState, b: &DroneState, sensor_range: f64, fov_deg: f64) -> f64 {
+    if sensor_range <= 0.0 || fov_deg <= 0.0 {
+        return 0.0;
+    }
+    let half_angle = (fov_deg.min(360.0) / 2.0).to_radians();
+    let cone = |state: &DroneState| {
+        let velocity = Vector3D::from(state.velocity.clone());
+        let axis = if velocity.magnitude() > 0.0 { velocity.normalize() } else { Vector3D::new(1.0, 0.0, 0.0) };
+        (Vector3D::from(state.position.clone()), axis)
+    };
+    let (apex_a, axis_a) = cone(a);
+    let (apex_b, axis_b) = cone(b);
+    
+    // Orthonormal frame around a's axis
+    let helper = if axis_a.x.abs() < 0.9 { Vector3D::new(1.0, 0.0, 0.0) } else { Vector3D::new(0.0, 1.0, 0.0) };
+    let u = axis_a.cross(&helper).normalize();
+    let v = axis_a.cross(&u);
+    
+    let mut rng = StdRng::seed_from_u64(0);
+    let cos_half = half_angle.cos();
+    let inside = (0..OVERLAP_SAMPLES)
+        .filter(|_| {
+            // Uniform in volume over the cone
+            let cos_theta = rng.gen_range(cos_half..=1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = rng.gen_range(0.0..std::f64::consts::TAU);
+            let r = sensor_range * rng.gen_range(0.0f64..=1.0).cbrt();
+            let direction = axis_a
+                .multiply(cos_theta)
+                .add(&u.multiply(sin_theta * phi.cos()))
+                .add(&v.multiply(sin_theta * phi.sin()));
+            let point = apex_a.add(&direction.multiply(r));
+            
+            let offset = point.subtract(&apex_b);
+            let distance = offset.magnitude();
+            distance <= sensor_range && (distance == 0.0 || offset.dot(&axis_b) / distance >= cos_half - 1e-12)
+        })
+        .count();
+    
+    inside as f64 / OVERLAP_SAMPLES as f64
+}
+
+/// Sum of `r × v` about the swarm centroid, with unit mass per drone. A
+/// large magnitude relative to the swarm's size and speed means milling.
+pub fn angular_momentum(states: &[DroneState]) -> Vector3D {
+    let centroid = swarm_centroid(states);
+    states.iter().fold(Vector3D::zero(), |total, state| {
+        let r = Vector3D::from(state.position.clone()).subtract(&centroid);
+        let v = Vector3D::from(state.velocity.clone());
+        total.add(&r.cross(&v))
+    })
+}
+
+/// Convex hull of the swarm's ground projection (x, y), counter-clockwise
+/// from the lowest-x vertex. Uses Andrew's monotone chain; drones on a hull
+/// edge or inside it are left out.
+pub fn swarm_hull_2d(states: &[DroneState]) -> Vec<(f64, f64)> {
+    let mut points: Vec<(f64, f64)> = states.iter().map(|s| (s.position.x, s.position.y)).collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    
+    // z of (a - o) x (b - o); positive for a left turn
+    let turn = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(points.len() * 2);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0 {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // Each chain's last point starts the other one
+        hull.pop();
+    }
+    
+    hull
+}
+
+/// Each drone's id with the angle in radians between its velocity and the
+/// mean velocity of the drones within `neighbor_radius`. Drones that are
+/// stationary, isolated, or among neighbors whose velocities cancel out
+/// report zero.
+pub fn local_alignment_errors(states: &[DroneState], neighbor_radius: f64) -> Vec<(String, f64)> {
+    states
+        .iter()
+        .enumerate()
+        .map(|(i, drone)| {
+            let position = Vector3D::from(drone.position.clone());
+            let mean_velocity = states
+                .iter()
+                .enumerate()
+                .filter(|&(j, other)| {
+                    j != i && Vector3D::from(other.position.clone()).distance_to(&position) <= neighbor_radius
+                })
+                .fold(Vector3D::zero(), |sum, (_, other)| sum.add(&Vector3D::from(other.velocity.clone())));
+            
+            let velocity = Vector3D::from(drone.velocity.clone());
+            let error = if velocity.magnitude() > 0.0 && mean_velocity.magnitude() > 0.0 {
+                velocity.normalize().dot(&mean_velocity.normalize()).clamp(-1.0, 1.0).acos()
+            } else {
+                0.0
+            };
+            (drone.id.clone(), error)
+        })
+        .collect()
+}
+
+/// Mean of `points` weighted by `weights`. None if the lengths differ or
+/// the weights sum to zero.
+pub fn weighted_centroid(points: &[Vector3D], weights: &[f64]) -> Option<Vector3D> {
+    let total: f64 = weights.iter().sum();
+    if points.len() != weights.len() || total == 0.0 || !total.is_finite() {
+        return None;
+    }
+    let sum = points
+        .iter()
+        .zip(weights)
+        .fold(Vector3D::zero(), |sum, (point, &weight)| sum.add(&point.multiply(weight)));
+    Some(sum.multiply(1.0 / total))
+}
+
+/// Vicsek order parameter: magnitude of the mean unit heading, from 1 for
+/// a perfectly aligned swarm down to about 0 for random headings.
+/// Stationary drones count toward the mean with no heading.
+pub fn order_parameter(states: &[DroneState]) -> f64 {
+    if states.is_empty() {
+        return 0.0;
+    }
+    let heading_sum = states
+        .iter()
+        .fold(Vector3D::zero(), |sum, s| sum.add(&Vector3D::from(s.velocity.clone()).normalize()));
+    heading_sum.magnitude() / states.len() as f64
+}
+
+/// Mean radial velocity about the swarm centroid, relative to the swarm's
+/// mean velocity: positive while the swarm spreads out, negative while it
+/// contracts, and unaffected by the swarm translating as a whole. Drones
+/// sitting on the centroid contribute nothing.
+pub fn expansion_rate(states: &[DroneState]) -> f64 {
+    if states.is_empty() {
+        return 0.0;
+    }
+    let center = swarm_centroid(states);
+    let mean_velocity = states
+        .iter()
+        .fold(Vector3D::zero(), |sum, s| sum.add(&Vector3D::from(s.velocity.clone())))
+        .multiply(1.0 / states.len() as f64);
+    let radial_sum: f64 = states
+        .iter()
+        .map(|s| {
+            let outward = Vector3D::from(s.position.clone()).subtract(&center).normalize();
+            Vector3D::from(s.velocity.clone()).subtract(&mean_velocity).dot(&outward)
+        })
+        .sum();
+    radial_sum / states.len() as f64
+}
+
+/// Eigenvalues of the position covariance about the centroid, largest
+/// first. One dominant value means a line, two a flat sheet, three
+/// similar ones a blob. An empty swarm gives zeros.
+pub fn shape_descriptor(states: &[DroneState]) -> (f64, f64, f64) {
+    if states.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let center = swarm_centroid(states);
+    let covariance = states
+        .iter()
+        .map(|s| {
+            let d = Vector3D::from(s.position.clone()).subtract(&center);
+            let d = nalgebra::Vector3::new(d.x, d.y, d.z);
+            d * d.transpose()
+        })
+        .fold(nalgebra::Matrix3::zeros(), |sum, outer| sum + outer)
+        / states.len() as f64;
+    
+    let mut eigenvalues: Vec<f64> = covariance.symmetric_eigenvalues().iter().copied().collect();
+    eigenvalues.sort_by(|a, b| b.total_cmp(a));
+    (eigenvalues[0], eigenvalues[1], eigenvalues[2])
+}
+
+/// Smallest sphere containing every drone, as (center, radius). Uses the
+/// incremental form of Welzl's algorithm on a fixed-seed shuffle, so the
+/// result is exact and the same for the same input.
+pub fn enclosing_sphere(states: &[DroneState]) -> (Vector3D, f64) {
+    let mut points: Vec<Vector3D> = states
+        .iter()
+        .map(|s| Vector3D::from(s.position.clone()))
+        .collect();
+    if points.is_empty() {
+        return (Vector3D::zero(), 0.0);
+    }
+    
+    // Random order keeps the expected running time linear
+    let mut rng = StdRng::seed_from_u64(0);
+    for i in (1..points.len()).rev() {
+        points.swap(i, rng.gen_range(0..=i));
+    }
+    
+    let mut sphere = (points[0].clone(), 0.0);
+    for i in 1..points.len() {
+        if sphere_contains(&sphere, &points[i]) {
+            continue;
+        }
+        sphere = (points[i].clone(), 0.0);
+        for j in 0..i {
+            if sphere_contains(&sphere, &points[j]) {
+                continue;
+            }
+            sphere = sphere_through_two(&points[i], &points[j]);
+            for k in 0..j {
+                if sphere_contains(&sphere, &points[k]) {
+                    continue;
+                }
+                sphere = sphere_through_three(&points[i], &points[j], &points[k]);
+                for l in 0..k {
+                    if !sphere_contains(&sphere, &points[l]) {
+                        sphere = sphere_through_four(&points[i], &points[j], &points[k], &points[l]);
+                    }
+                }
+            }
+        }
+    }
+    
+    sphere
+}
+
+fn sphere_contains(sphere: &(Vector3D, f64), point: &Vector3D) -> bool {
+    sphere.0.distance_to(point) <= sphere.1 * (1.0 + 1e-9) + 1e-9
+}
+
+fn sphere_through_two(a: &Vector3D, b: &Vector3D) -> (Vector3D, f64) {
+    let center = a.add(b).multiply(0.5);
+    let radius = center.distance_to(a);
+    (center, radius)
+}
+
+// Circumcircle of the triangle, which is the smallest sphere through all three
+fn sphere_through_three(a: &Vector3D, b: &Vector3D, c: &Vector3D) -> (Vector3D, f64) {
+    let ab = b.subtract(a);
+    let ac = c.subtract(a);
+    let normal = ab.cross(&ac);
+    let denominator = 2.0 * normal.dot(&normal);
+    
+    // Collinear points: the two farthest apart span the sphere
+    if denominator < 1e-12 {
+        return [sphere_through_two(a, b), sphere_through_two(a, c), sphere_through_two(b, c)]
+            .into_iter()
+            .fold((Vector3D::zero(), f64::NEG_INFINITY), |best, s| if s.1 > best.1 { s } else { best });
+    }
+    
+    let offset = ac
+        .multiply(ab.dot(&ab))
+        .subtract(&ab.multiply(ac.dot(&ac)))
+        .cross(&normal)
+        .multiply(1.0 / denominator);
+    let center = a.add(&offset);
+    (center, offset.magnitude())
+}
+
+fn sphere_through_four(a: &Vector3D, b: &Vector3D, c: &Vector3D, d: &Vector3D) -> (Vector3D, f64) {
+    let ab = b.subtract(a);
+    let ac = c.subtract(a);
+    let ad = d.subtract(a);
+    let denominator = 2.0 * ab.dot(&ac.cross(&ad));
+    
+    // Coplanar points have no unique circumsphere
+    if denominator.abs() < 1e-12 {
+        return [
+            sphere_through_three(a, b, c),
+            sphere_through_three(a, b, d),
+            sphere_through_three(a, c, d),
+            sphere_through_three(b, c, d),
+        ]
+        .into_iter()
+        .filter(|s| [a, b, c, d].iter().all(|p| sphere_contains(s, p)))
+        .fold((Vector3D::zero(), f64::INFINITY), |best, s| if s.1 < best.1 { s } else { best });
+    }
+    
+    let offset = ac
+        .cross(&ad)
+        .multiply(ab.dot(&ab))
+        .add(&ad.cross(&ab).multiply(ac.dot(&ac)))
+        .add(&ab.cross(&ac).multiply(ad.dot(&ad)))
+        .multiply(1.0 / denominator);
+    let center = a.add(&offset);
+    (center, offset.magnitude())
+}
+
+/// Expresses the swarm relative to its centroid, rotated so the mean
+/// heading points along +x, climbing or diving included. Returns
+/// (position, velocity) per drone.
+pub fn to_swarm_frame(states: &[DroneState]) -> Vec<(Vector3D, Vector3D)> {
+    if states.is_empty() {
+        return Vec::new();
+    }
+    
+    let count = states.len() as f64;
+    let mut centroid = Vector3D::zero();
+    let mut mean_velocity = Vector3D::zero();
+    
+    for state in states {
+        centroid = centroid.add(&Vector3D::from(state.position.clone()));
+        mean_velocity = mean_velocity.add(&Vector3D::from(state.velocity.clone()));
+    }
+    
+    centroid = centroid.multiply(1.0 / count);
+    mean_velocity = mean_velocity.multiply(1.0 / count);
+    
+    // A stationary swarm has no heading, so only translate it. A heading
+    // straight along -x has no unique shortest rotation; turn it about z.
+    let heading = nalgebra::Vector3::new(mean_velocity.x, mean_velocity.y, mean_velocity.z);
+    let rotation = if heading.norm() > 0.0 {
+        nalgebra::Rotation3::rotation_between(&heading, &nalgebra::Vector3::x()).unwrap_or_else(|| {
+            nalgebra::Rotation3::from_axis_angle(&nalgebra::Vector3::z_axis(), std::f64::consts::PI)
+        })
+    } else {
+        nalgebra::Rotation3::identity()
+    };
+    let rotate = |v: Vector3D| {
+        let rotated = rotation * nalgebra::Vector3::new(v.x, v.y, v.z);
+        Vector3D::new(rotated.x, rotated.y, rotated.z)
+    };
+    
+    states
+        .iter()
+        .map(|state| {
+            let offset = Vector3D::from(state.position.clone()).subtract(&centroid);
+            let velocity = Vector3D::from(state.velocity.clone());
+            (rotate(offset), rotate(velocity))
+        })
+        .collect()
+}
+
+/// Axis-aligned world bounds. `min.z` is treated as the ground.
+#[derive(Debug, Clone)]
+pub struct BoundingBox {
+    pub min: Vector3D,
+    pub max: Vector3D,
+}
+
+impl BoundingBox {
+    pub fn new(min: Vector3D, max: Vector3D) -> Self {
+        Self { min, max }
+    }
+    
+    pub fn from_corners((min, max): BoxCorners) -> Self {
+        Self::new(Vector3D::new(min.0, min.1, min.2), Vector3D::new(max.0, max.1, max.2))
+    }
+    
+    /// Hard-walled bounce: a drone that has crossed a face while moving
+    /// outward is mirrored back inside with its velocity reflected.
+    pub fn bounce(&self, position: &Vector3D, velocity: &Vector3D) -> (Vector3D, Vector3D) {
+        let mut position = position.clone();
+        let mut velocity = velocity.clone();
+        
+        let faces = [
+            (position.x < self.min.x && velocity.x < 0.0, Vector3D::new(1.0, 0.0, 0.0)),
+            (position.x > self.max.x && velocity.x > 0.0, Vector3D::new(-1.0, 0.0, 0.0)),
+            (position.y < self.min.y && velocity.y < 0.0, Vector3D::new(0.0, 1.0, 0.0)),
+            (position.y > self.max.y && velocity.y > 0.0, Vector3D::new(0.0, -1.0, 0.0)),
+            (position.z < self.min.z && velocity.z < 0.0, Vector3D::new(0.0, 0.0, 1.0)),
+            (position.z > self.max.z && velocity.z > 0.0, Vector3D::new(0.0, 0.0, -1.0)),
+        ];
+        for (crossed, normal) in faces {
+            if crossed {
+                velocity = velocity.reflect(&normal);
+            }
+        }
+        
+        position.x = mirror_into(position.x, self.min.x, self.max.x);
+        position.y = mirror_into(position.y, self.min.y, self.max.y);
+        position.z = mirror_into(position.z, self.min.z, self.max.z);
+        
+        (position, velocity)
+    }
+    
+    /// Box centered on the origin in x/y, spanning `0..z_bound` in z.
+    pub fn centered(world_bounds: (f64, f64, f64)) -> Self {
+        let (x_bound, y_bound, z_bound) = world_bounds;
+        Self::new(
+            Vector3D::new(-x_bound / 2.0, -y_bound / 2.0, 0.0),
+            Vector3D::new(x_bound / 2.0, y_bound / 2.0, z_bound),
+        )
+    }
+}
+
+// Mirrors an overshoot past either end back inside `min..max`
+fn mirror_into(value: f64, min: f64, max: f64) -> f64 {
+    if value < min {
+        (2.0 * min - value).min(max)
+    } else if value > max {
+        (2.0 * max - value).max(min)
+    } else {
+        value
+    }
+}
+
+pub fn boundary_force_box(
+    position: &Vector3D,
+    _velocity: &Vector3D,
+    bounds: &BoundingBox,
+    boundary_margin: f64,
+) -> Vector3D {
+    let mut boundary_force = Vector3D::zero();
+    
+    // X boundaries
+    if position.x < bounds.min.x + boundary_margin {
+        boundary_force.x += ((bounds.min.x + boundary_margin) - position.x) * 0.1;
+    } else if position.x > bounds.max.x - boundary_margin {
+        boundary_force.x -= (position.x - (bounds.max.x - boundary_margin)) * 0.1;
+    }
+    
+    // Y boundaries
+    if position.y < bounds.min.y + boundary_margin {
+        boundary_force.y += ((bounds.min.y + boundary_margin) - position.y) * 0.1;
+    } else if position.y > bounds.max.y - boundary_margin {
+        boundary_force.y -= (position.y - (bounds.max.y - boundary_margin)) * 0.1;
+    }
+    
+    // Z boundaries (ground and ceiling)
+    if position.z < bounds.min.z + boundary_margin {
+        boundary_force.z += ((bounds.min.z + boundary_margin) - position.z) * 0.2;
+    } else if position.z > bounds.max.z - boundary_margin {
+        boundary_force.z -= (position.z - (bounds.max.z - boundary_margin)) * 0.1;
+    }
+    
+    boundary_force
+}
+
+pub fn calculate_obstacle_avoidance(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    obstacles: &[(f64, f64, f64, f64)], // (x, y, z, radius)
+    params: &AvoidanceParams,
+) -> Vector3D {
+    obstacles.iter().fold(Vector3D::zero(), |force, &(ox, oy, oz, radius)| {
+        force.add(&avoidance_from(position, velocity, &Vector3D::new(ox, oy, oz), radius, params))
+    })
+}
+
+/// Debounce band for avoidance: it switches on once a drone's clearance
+/// drops below `enter_distance` and stays on until the clearance exceeds
+/// `exit_distance`.
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "AvoidanceHysteresis"]
+pub struct AvoidanceHysteresis {
+    pub enter_distance: f64,
+    pub exit_distance: f64,
+}
+
+/// Sphere avoidance with hysteresis. `active` holds each obstacle's state
+/// from the previous call (missing entries count as inactive) and is
+/// updated in place. Active obstacles repel as if `avoidance_distance`
+/// were `exit_distance`, so the force fades out at the exit boundary.
+pub fn calculate_obstacle_avoidance_with_hysteresis(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    obstacles: &[(f64, f64, f64, f64)],
+    params: &AvoidanceParams,
+    hysteresis: &AvoidanceHysteresis,
+    active: &mut Vec<bool>,
+) -> Vector3D {
+    active.resize(obstacles.len(), false);
+    let latched = AvoidanceParams { avoidance_distance: hysteresis.exit_distance, ..params.clone() };
+    
+    obstacles
+        .iter()
+        .zip(active.iter_mut())
+        .fold(Vector3D::zero(), |force, (&(ox, oy, oz, radius), was_active)| {
+            let center = Vector3D::new(ox, oy, oz);
+            let clearance = position.distance_to(&center) - radius - params.drone_radius;
+            let threshold = if *was_active { hysteresis.exit_distance } else { hysteresis.enter_distance };
+            *was_active = clearance < threshold;
+            
+            if *was_active {
+                force.add(&avoidance_from(position, velocity, &center, radius, &latched))
+            } else {
+                force
+            }
+        })
+}
+
+/// Avoidance against capsules. Each capsule repels from the point on its
+/// axis closest to the drone, so a drone beside a long beam is pushed
+/// straight away from it rather than toward either end.
+pub fn calculate_capsule_avoidance(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    capsules: &[Capsule],
+    params: &AvoidanceParams,
+) -> Vector3D {
+    capsules.iter().fold(Vector3D::zero(), |force, capsule| {
+        let closest = capsule.closest_point(position);
+        force.add(&avoidance_from(position, velocity, &closest, capsule.radius, params))
+    })
+}
+
+// Repulsion from a sphere of `radius` around `center`
+fn avoidance_from(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    center: &Vector3D,
+    radius: f64,
+    params: &AvoidanceParams,
+) -> Vector3D {
+    let distance = position.distance_to(center);
+    let danger_distance = radius + params.drone_radius + params.avoidance_distance;
+    if distance >= danger_distance || distance <= 0.0 {
+        return Vector3D::zero();
+    }
+    if ignored_behind(position, velocity, center, params) {
+        return Vector3D::zero();
+    }
+    
+    // Calculate avoidance vector
+    let avoidance_dir = position.subtract(center).normalize();
+    
+    // Strength inversely proportional to distance
+    let strength = (danger_distance - distance) / danger_distance;
+    let mut avoidance_force = avoidance_dir.multiply(strength * 2.0);
+    
+    // Steer around rather than only bouncing off when approaching
+    if params.tangential_weight > 0.0 && velocity.dot(&avoidance_dir) < 0.0 {
+        let tangent = steer_around_direction(velocity, &avoidance_dir);
+        avoidance_force = avoidance_force.add(&tangent.multiply(strength * 2.0 * params.tangential_weight));
+    }
+    
+    avoidance_force
+}
+
+// Whether `forward_only` hides an obstacle at `center` from a drone moving
+// at `velocity`
+fn ignored_behind(position: &Vector3D, velocity: &Vector3D, center: &Vector3D, params: &AvoidanceParams) -> bool {
+    params.forward_only && velocity.magnitude() > 0.0 && velocity.dot(&center.subtract(position)) <= 0.0
+}
+
+// Point on the segment `from`-`to` closest to `point`
+fn closest_point_on_segment(from: &Vector3D, to: &Vector3D, point: &Vector3D) -> Vector3D {
+    let segment = to.subtract(from);
+    let length_sq = segment.dot(&segment);
+    let t = if length_sq > 0.0 {
+        (point.subtract(from).dot(&segment) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    from.add(&segment.multiply(t))
+}
+
+/// Whether the straight segment `from`-`to` stays at least `clearance`
+/// away from every obstacle's surface.
+pub fn path_clear(
+    from: &Vector3D,
+    to: &Vector3D,
+    obstacles: &[(f64, f64, f64, f64)],
+    clearance: f64,
+) -> bool {
+    obstacles.iter().all(|&(ox, oy, oz, radius)| {
+        let center = Vector3D::new(ox, oy, oz);
+        closest_point_on_segment(from, to, &center).distance_to(&center) > radius + clearance
+    })
+}
+
+/// Distance along the drone's heading to the first obstacle surface, or
+/// `max_range` if the ray is clear that far. A drone already inside an
+/// obstacle reports zero; a stationary one has no heading and reports
+/// `max_range`.
+pub fn forward_clearance(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    obstacles: &[(f64, f64, f64, f64)],
+    max_range: f64,
+) -> f64 {
+    if velocity.magnitude() == 0.0 {
+        return max_range;
+    }
+    let heading = velocity.normalize();
+    
+    obstacles
+        .iter()
+        .filter_map(|&(ox, oy, oz, radius)| {
+            let to_center = Vector3D::new(ox, oy, oz).subtract(position);
+            let c = to_center.dot(&to_center) - radius * radius;
+            if c <= 0.0 {
+                return Some(0.0);
+            }
+            // Nearest root of |position + t * heading - center| = radius
+            let along = to_center.dot(&heading);
+            let discriminant = along * along - c;
+            if along <= 0.0 || discriminant < 0.0 {
+                return None;
+            }
+            Some(along - discriminant.sqrt())
+        })
+        .fold(max_range, f64::min)
+}
+
+/// Repulsion straight from a LiDAR point cloud in the sensor frame (drone
+/// at the origin). Every point closer than `avoidance_distance` pushes
+/// away with strength `1 / distance`; the sum is limited to `max_force`.
+pub fn lidar_repulsion(points: &[(f32, f32, f32)], avoidance_distance: f64, max_force: f64) -> Vector3D {
+    points
+        .iter()
+        .map(|&(x, y, z)| Vector3D::new(x as f64, y as f64, z as f64))
+        .filter(|point| {
+            let distance = point.magnitude();
+            distance > 0.0 && distance < avoidance_distance
+        })
+        .fold(Vector3D::zero(), |force, point| {
+            let distance = point.magnitude();
+            force.subtract(&point.normalize().multiply(1.0 / distance))
+        })
+        .limit(max_force)
+}
+
+/// Adds weighted obstacle avoidance to `flocking_force` and limits the
+/// result to `max_force`. In priority mode a critically close obstacle
+/// drops the flocking force so avoidance alone steers the drone, as long
+/// as there is any avoidance to steer by.
+pub fn combine_with_avoidance(
+    flocking_force: &Vector3D,
+    position: &Vector3D,
+    velocity: &Vector3D,
+    obstacles: &[(f64, f64, f64, f64)],
+    params: &FlockingParams,
+    avoidance: &AvoidanceParams,
+) -> Vector3D {
+    let critical = avoidance.avoidance_priority
+        && obstacles.iter().any(|&(ox, oy, oz, radius)| {
+            let center = Vector3D::new(ox, oy, oz);
+            !ignored_behind(position, velocity, &center, avoidance)
+                && position.distance_to(&center) - radius - avoidance.drone_radius < avoidance.critical_distance
+        });
+    
+    // Obstacles already folded into separation get no second avoidance
+    // force, unless a critical encounter is about to drop that separation
+    let avoid = if params.obstacles_as_neighbors && !critical {
+        Vector3D::zero()
+    } else {
+        calculate_obstacle_avoidance(position, velocity, obstacles, avoidance)
+            .multiply(params.obstacle_avoidance_weight)
+    };
+    let avoid = if avoidance.speed_scaled_avoidance && params.max_speed > 0.0 && !critical {
+        avoid.multiply((velocity.magnitude() / params.max_speed).clamp(0.0, 1.0))
+    } else {
+        avoid
+    };
+    
+    if critical && avoid.magnitude() > 0.0 {
+        return avoid.limit(params.max_force);
+    }
+    
+    flocking_force.add(&avoid).limit(params.max_force)
+}
+
+// Direction perpendicular to the obstacle normal that keeps whatever sideways
+// motion the drone already has. Head-on approaches have none, so they veer
+// horizontally instead.
+fn steer_around_direction(velocity: &Vector3D, normal: &Vector3D) -> Vector3D {
+    let mut axis = velocity.cross(normal);
+    if axis.magnitude() < 1e-9 {
+        axis = Vector3D::new(0.0, 0.0, 1.0);
+        if axis.cross(normal).magnitude() < 1e-9 {
+            axis = Vector3D::new(1.0, 0.0, 0.0);
+        }
+    }
+    
+    normal.cross(&axis).normalize()
+}
+
+/// Avoidance against classified obstacles. Only obstacles whose type is in
+/// `avoid_types` contribute; an empty list avoids every type.
+pub fn calculate_typed_obstacle_avoidance(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    obstacles: &[Obstacle],
+    params: &AvoidanceParams,
+    avoid_types: &[String],
+) -> Vector3D {
+    let spheres: Vec<(f64, f64, f64, f64)> = obstacles
+        .iter()
+        .filter(|obstacle| avoid_types.is_empty() || avoid_types.contains(&obstacle.obstacle_type))
+        .map(|obstacle| {
+            let (x, y, z) = obstacle.position;
+            (x as f64, y as f64, z as f64, obstacle.size as f64)
+        })
+        .collect();
+    
+    calculate_obstacle_avoidance(position, velocity, &spheres, params)
+}
+
+pub fn integrate_motion(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    acceleration: &Vector3D,
+    dt: f64,
+    max_speed: f64,
+) -> (Vector3D, Vector3D) {
+    // Update velocity with acceleration
+    let new_velocity = velocity.add(&acceleration.multiply(dt));
+    let limited_velocity = new_velocity.limit(max_speed);
+    
+    // Update position with velocity
+    let new_position = position.add(&limited_velocity.multiply(dt));
+    
+    (new_position, limited_velocity)
+}
+
+/// RK4 step for constant acceleration. Each stage's velocity respects
+/// `max_speed`; below the cap this matches the exact ballistic solution.
+pub fn integrate_motion_rk4(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    acceleration: &Vector3D,
+    dt: f64,
+    max_speed: f64,
+) -> (Vector3D, Vector3D) {
+    let k1 = velocity.limit(max_speed);
+    let k2 = velocity.add(&acceleration.multiply(dt / 2.0)).limit(max_speed);
+    let k3 = k2.clone();
+    let k4 = velocity.add(&acceleration.multiply(dt)).limit(max_speed);
+    
+    let mean_velocity = k1
+        .add(&k2.multiply(2.0))
+        .add(&k3.multiply(2.0))
+        .add(&k4)
+        .multiply(1.0 / 6.0);
+    
+    (position.add(&mean_velocity.multiply(dt)), k4)
+}
+
+/// Integrates motion, then wraps the new position back into the world.
+pub fn integrate_motion_in_topology(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    acceleration: &Vector3D,
+    dt: f64,
+    max_speed: f64,
+    topology: &Topology,
+    integration: &IntegrationParams,
+) -> (Vector3D, Vector3D) {
+    let (new_position, new_velocity) = integration.integrate(position, velocity, acceleration, dt, max_speed);
+    (topology.wrap(&new_position), new_velocity)
+}
+
+/// Adjusts a desired velocity as little as possible so it leaves the
+/// velocity-obstacle cone of each neighbor that it would otherwise hit
+/// within `horizon` seconds. Neighbors are handled one at a time.
+pub fn safe_velocity(
+    position: &Vector3D,
+    desired: &Vector3D,
+    neighbors: &[DroneState],
+    combined_radius: f64,
+    horizon: f64,
+) -> Vector3D {
+    let mut velocity = desired.clone();
+    
+    for neighbor in neighbors {
+        let offset = Vector3D::from(neighbor.position.clone()).subtract(position);
+        let neighbor_vel = Vector3D::from(neighbor.velocity.clone());
+        let relative = velocity.subtract(&neighbor_vel);
+        
+        if let Some(adjusted) = leave_velocity_obstacle(&relative, &offset, combined_radius, horizon) {
+            velocity = neighbor_vel.add(&adjusted);
+        }
+    }
+    
+    velocity
+}
+
+// Projects a relative velocity onto the nearest edge of the collision cone
+// around `offset`, or returns None when it is already collision-free
+fn leave_velocity_obstacle(
+    relative: &Vector3D,
+    offset: &Vector3D,
+    radius: f64,
+    horizon: f64,
+) -> Option<Vector3D> {
+    let distance = offset.magnitude();
+    let toward = offset.normalize();
+    let closing = relative.dot(&toward);
+    if closing <= 0.0 {
+        return None;
+    }
+    
+    // Already overlapping: just stop closing in
+    if distance <= radius {
+        return Some(relative.subtract(&toward.multiply(closing)));
+    }
+    
+    // Earliest time the relative path enters the combined radius
+    let speed_sq = relative.dot(relative);
+    let discriminant = closing * distance * closing * distance
+        - speed_sq * (distance * distance - radius * radius);
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time_to_collision = (closing * distance - discriminant.sqrt()) / speed_sq;
+    if time_to_collision > horizon {
+        return None;
+    }
+    
+    let mut sideways = relative.subtract(&toward.multiply(closing));
+    if sideways.magnitude() < 1e-9 {
+        sideways = toward.cross(&Vector3D::new(0.0, 0.0, 1.0));
+        if sideways.magnitude() < 1e-9 {
+            sideways = toward.cross(&Vector3D::new(1.0, 0.0, 0.0));
+        }
+    }
+    
+    let half_angle = (radius / distance).asin();
+    let edge = toward
+        .multiply(half_angle.cos())
+        .add(&sideways.normalize().multiply(half_angle.sin()));
+    Some(edge.multiply(relative.dot(&edge).max(0.0)))
+}
+
+/// Thrust needed to achieve `desired_acceleration` while holding against
+/// gravity acting along -z.
+pub fn required_thrust(desired_acceleration: &Vector3D, gravity: f64, mass: f64) -> Vector3D {
+    desired_acceleration
+        .add(&Vector3D::new(0.0, 0.0, gravity))
+        .multiply(mass)
+}
+
+/// (yaw, pitch) in radians of the direction from `from` to `target`. Yaw
+/// is measured counter-clockwise from +x (east) in the xy plane, so +y
+/// (north) is π/2; pitch is the climb angle above the horizontal.
+pub fn heading_to(from: &Vector3D, target: &Vector3D) -> (f64, f64) {
+    let offset = target.subtract(from);
+    let horizontal = (offset.x * offset.x + offset.y * offset.y).sqrt();
+    (offset.y.atan2(offset.x), offset.z.atan2(horizontal))
+}
+
+/// Earliest point where a pursuer flying straight at `pursuer_speed` can
+/// meet a target holding its current velocity, or None if it never can
+/// (a target moving away faster than the pursuer, or a non-positive speed).
+pub fn intercept_point(
+    pursuer: &Vector3D,
+    target: &Vector3D,
+    target_velocity: &Vector3D,
+    pursuer_speed: f64,
+) -> Option<Vector3D> {
+    if pursuer_speed.is_nan() || pursuer_speed <= 0.0 {
+        return None;
+    }
+    
+    // |offset + v t| = speed t, as a t² + b t + c = 0
+    let offset = target.subtract(pursuer);
+    let a = target_velocity.dot(target_velocity) - pursuer_speed * pursuer_speed;
+    let b = 2.0 * offset.dot(target_velocity);
+    let c = offset.dot(&offset);
+    
+    let time = if a.abs() < 1e-12 {
+        // Equal speeds: the quadratic degenerates to a line
+        if b < 0.0 { Some(-c / b) } else { None }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+                .into_iter()
+                .filter(|t| *t >= 0.0)
+                .min_by(|x, y| x.total_cmp(y))
+        }
+    }?;
+    Some(target.add(&target_velocity.multiply(time)))
+}
+
+/// True when the drone is nearly still and the net acceleration on it
+/// (thrust already balanced against gravity) is nearly zero.
+pub fn is_hovering(velocity: &Vector3D, speed_eps: f64, net_acceleration: &Vector3D, accel_eps: f64) -> bool {
+    velocity.magnitude() < speed_eps && net_acceleration.magnitude() < accel_eps
+}
+
+/// Rolls a drone forward under constant acceleration, returning the
+/// position after each of `steps` integration steps.
+pub fn predict_trajectory(
+    position: &Vector3D,
+    velocity: &Vector3D,
+    acceleration: &Vector3D,
+    dt: f64,
+    steps: usize,
+    max_speed: f64,
+) -> Vec<Vector3D> {
+    let mut position = position.clone();
+    let mut velocity = velocity.clone();
+    let mut trajectory = Vec::with_capacity(steps);
+    
+    for _ in 0..steps {
+        let (new_position, new_velocity) = integrate_motion(&position, &velocity, acceleration, dt, max_speed);
+        trajectory.push(new_position.clone());
+        position = new_position;
+        velocity = new_velocity;
+    }
+    
+    trajectory
+}
+
+/// Minimum-jerk move from rest at `start` to rest at `goal` over
+/// `duration` seconds, sampled at `steps + 1` evenly spaced times from
+/// start to goal inclusive. Follows the quintic 10τ³ - 15τ⁴ + 6τ⁵, which
+/// has zero velocity and acceleration at both ends.
+pub fn min_jerk_trajectory(start: &Vector3D, goal: &Vector3D, duration: f64, steps: usize) -> Vec<Vector3D> {
+    let travel = goal.subtract(start);
+    (0..=steps)
+        .map(|k| {
+            let t = duration * k as f64 / steps.max(1) as f64;
+            let tau = (t / duration).clamp(0.0, 1.0);
+            let blend = tau * tau * tau * (10.0 - 15.0 * tau + 6.0 * tau * tau);
+            start.add(&travel.multiply(blend))
+        })
+        .collect()
+}
+
+/// Ramer-Douglas-Peucker simplification: keeps the endpoints and every
+/// point that lies further than `epsilon` from the simplified path.
+pub fn simplify_trajectory(points: &[Vector3D], epsilon: f64) -> Vec<Vector3D> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    
+    // Spans still to check, as (first, last) indices
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| {
+                let closest = closest_point_on_segment(&points[first], &points[last], &points[i]);
+                (i, closest.distance_to(&points[i]))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        
+        if let Some((index, distance)) = farthest {
+            if distance > epsilon {
+                keep[index] = true;
+                spans.push((first, index));
+                spans.push((index, last));
+            }
+        }
+    }
+    
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, kept)| *kept)
+        .map(|(point, _)| point.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drone;
+    
+    #[test]
+    fn test_vector3d_operations() {
+        let v1 = Vector3D::new(1.0, 2.0, 3.0);
+        let v2 = Vector3D::new(4.0, 5.0, 6.0);
+        
+        let sum = v1.add(&v2);
+        assert_eq!(sum.x, 5.0);
+        assert_eq!(sum.y, 7.0);
+        assert_eq!(sum.z, 9.0);
+        
+        let magnitude = v1.magnitude();
+        assert!((magnitude - 3.7416573868).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_separation_force() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let neighbor = drone("test", (10.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        
+        let neighbors = vec![&neighbor];
+        let params = FlockingParams::default();
+        
+        let force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &params);
+        
+        // Should point away from neighbor (negative x direction)
+        assert!(force.x < 0.0);
+        assert!(force.magnitude() > 0.0);
+    }
+    
+    #[test]
+    fn test_separation_full_radius() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let neighbor = drone("test", (80.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        let neighbors = vec![&neighbor];
+        
+        let params = FlockingParams::default();
+        let force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &params);
+        assert_eq!(force.magnitude(), 0.0);
+        
+        let params = FlockingParams { separation_full_radius: true, ..FlockingParams::default() };
+        let force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &params);
+        assert!(force.x < 0.0);
+    }
+    
+    #[test]
+    fn test_separation_min_distance_bounds_contact() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let make = |id: &str, x: f64, y: f64| drone(id, (x, y, 0.0), (0.0, 0.0, 0.0));
+        let touching = make("touching", 1e-12, 0.0);
+        let other = make("other", 0.0, 1.0);
+        let neighbors = vec![&touching, &other];
+        
+        // Without a floor the near-coincident neighbor swamps everything else
+        let params = FlockingParams::default();
+        let force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &params);
+        assert!(force.y.abs() < 1e-9);
+        
+        let params = FlockingParams { separation_min_distance: 1.0, ..FlockingParams::default() };
+        let force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &params);
+        assert!(force.x.is_finite() && force.y.is_finite());
+        assert!((force.magnitude() - 1.0).abs() < 1e-9);
+        assert!((force.x - force.y).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_alignment_speed_gate() {
+        let velocity = Vector3D::new(1.0, 0.0, 0.0);
+        let make = |id: &str, vx: f64, vy: f64| drone(id, (5.0, 0.0, 0.0), (vx, vy, 0.0));
+        let a = make("a", 0.01, -0.02);
+        let b = make("b", -0.03, 0.01);
+        let neighbors = vec![&a, &b];
+        
+        let params = FlockingParams::default();
+        assert!(calculate_alignment(&velocity, &neighbors, &params).magnitude() > 0.0);
+        
+        let params = FlockingParams { min_speed_for_alignment: 0.5, ..FlockingParams::default() };
+        assert_eq!(calculate_alignment(&velocity, &neighbors, &params).magnitude(), 0.0);
+    }
+    
+    #[test]
+    fn test_validate_separation_radius() {
+        assert!(FlockingParams::default().validate().is_empty());
+        
+        let params = FlockingParams { separation_radius: 150.0, ..FlockingParams::default() };
+        assert_eq!(params.validate().len(), 1);
+        
+        let params = FlockingParams { separation_full_radius: true, ..params };
+        assert!(params.validate().is_empty());
+    }
+    
+    #[test]
+    fn test_single_neighbor_contribution_is_total() {
+        let neighbors = vec![drone("other", (20.0, 10.0, 0.0), (0.0, 2.0, 0.0))];
+        let drone = drone("self", (0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        let params = FlockingParams::default();
+        
+        let contributions = per_neighbor_forces(&drone, &neighbors, &params);
+        let total = calculate_boids_forces(&drone, &neighbors, &params);
+        
+        assert_eq!(contributions.len(), 1);
+        assert_eq!(contributions[0].0, "other");
+        assert!(contributions[0].1.distance_to(&total) < 1e-12);
+    }
+    
+    fn mean_pairwise_distance(states: &[DroneState]) -> f64 {
+        let mut total = 0.0;
+        let mut pairs = 0;
+        for (i, a) in states.iter().enumerate() {
+            for b in &states[i + 1..] {
+                let a_pos = Vector3D::from(a.position.clone());
+                total += a_pos.distance_to(&Vector3D::from(b.position.clone()));
+                pairs += 1;
+            }
+        }
+        total / pairs as f64
+    }
+    
+    fn spread_swarm(spacing: f64) -> Vec<DroneState> {
+        (0..6)
+            .map(|i| {
+                let position = ((i % 3) as f64 * spacing, (i / 3) as f64 * spacing, 50.0);
+                drone(&format!("drone_{}", i), position, (0.0, 0.0, 0.0))
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_parallel_step_matches_serial_exactly() {
+        let params = FlockingParams::default();
+        let serial = StepParams::default();
+        let parallel = StepParams { parallel: true, ..StepParams::default() };
+        let bits = |states: &[DroneState]| -> Vec<[u64; 6]> {
+            states
+                .iter()
+                .map(|s| {
+                    [s.position.x, s.position.y, s.position.z, s.velocity.vx, s.velocity.vy, s.velocity.vz]
+                        .map(f64::to_bits)
+                })
+                .collect()
+        };
+        
+        let mut expected = spread_swarm(7.0);
+        for _ in 0..10 {
+            expected = step_swarm(&expected, &params, &serial, 0.1);
+        }
+        for _ in 0..20 {
+            let mut states = spread_swarm(7.0);
+            for _ in 0..10 {
+                states = step_swarm(&states, &params, &parallel, 0.1);
+            }
+            assert_eq!(bits(&states), bits(&expected));
+        }
+    }
+    
+    #[test]
+    fn test_swarm_modes_disperse_and_gather() {
+        let params = FlockingParams::default();
+        
+        let disperse = StepParams { mode: SwarmMode::Disperse, ..StepParams::default() };
+        let mut states = spread_swarm(10.0);
+        let mut previous = mean_pairwise_distance(&states);
+        for _ in 0..10 {
+            states = step_swarm(&states, &params, &disperse, 0.1);
+            let spread = mean_pairwise_distance(&states);
+            assert!(spread > previous);
+            previous = spread;
+        }
+        
+        let gather = StepParams {
+            mode: SwarmMode::Gather { point: (40.0, 20.0, 50.0) },
+            ..StepParams::default()
+        };
+        let mut states = spread_swarm(80.0);
+        let mut previous = mean_pairwise_distance(&states);
+        for _ in 0..10 {
+            states = step_swarm(&states, &params, &gather, 0.1);
+            let spread = mean_pairwise_distance(&states);
+            assert!(spread < previous);
+            previous = spread;
+        }
+    }
+    
+    #[test]
+    fn test_suggested_radius_hits_target_neighbors() {
+        use rand::{Rng, SeedableRng};
+        
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let states: Vec<DroneState> = (0..80)
+            .map(|i| {
+                let position = (rng.gen_range(0.0..200.0), rng.gen_range(0.0..200.0), rng.gen_range(0.0..200.0));
+                drone(&format!("drone_{}", i), position, (0.0, 0.0, 0.0))
+            })
+            .collect();
+        
+        let target = 6;
+        let params = FlockingParams {
+            neighbor_radius: suggest_neighbor_radius(&states, target),
+            ..FlockingParams::default()
+        };
+        
+        // Count neighbors the same way the batch step filters them
+        let total: usize = states
+            .iter()
+            .enumerate()
+            .map(|(i, drone)| {
+                let position = Vector3D::from(drone.position.clone());
+                states
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, other)| {
+                        *j != i
+                            && params.topology.distance(&position, &Vector3D::from(other.position.clone()))
+                                <= params.neighbor_radius
+                    })
+                    .count()
+            })
+            .sum();
+        let average = total as f64 / states.len() as f64;
+        
+        assert!((average - target as f64).abs() <= 0.5);
+    }
+    
+    #[test]
+    fn test_low_energy_returns_to_base() {
+        let make = |id: &str, x: f64, y: f64| drone(id, (x, y, 0.0), (0.0, 0.0, 0.0));
+        let drone = make("low", 0.0, 0.0);
+        let neighbors = vec![make("a", 20.0, 0.0), make("b", 0.0, 20.0), make("c", 10.0, 10.0)];
+        let params = FlockingParams::default();
+        let base = Vector3D::new(-100.0, -100.0, 0.0);
+        
+        let force = calculate_forces_with_energy(&drone, &neighbors, &params, 0.1, Some(&base), 0.2);
+        let to_base = base.normalize();
+        let direction = force.normalize();
+        assert!((direction.x - to_base.x).abs() < 1e-9);
+        assert!((direction.y - to_base.y).abs() < 1e-9);
+        
+        // Above the threshold the drone keeps flocking
+        let force = calculate_forces_with_energy(&drone, &neighbors, &params, 0.9, Some(&base), 0.2);
+        let flocking = calculate_boids_forces(&drone, &neighbors, &params);
+        assert_eq!(force.x, flocking.x);
+        assert_eq!(force.y, flocking.y);
+    }
+    
+    #[test]
+    fn test_offset_box_pushes_back_from_each_face() {
+        let bounds = BoundingBox::new(Vector3D::new(100.0, 200.0, 50.0), Vector3D::new(300.0, 260.0, 90.0));
+        let velocity = Vector3D::zero();
+        let margin = 5.0;
+        let center = Vector3D::new(200.0, 230.0, 70.0);
+        
+        assert_eq!(boundary_force_box(&center, &velocity, &bounds, margin).magnitude(), 0.0);
+        
+        let near = |x, y, z| boundary_force_box(&Vector3D::new(x, y, z), &velocity, &bounds, margin);
+        assert!(near(101.0, 230.0, 70.0).x > 0.0);
+        assert!(near(299.0, 230.0, 70.0).x < 0.0);
+        assert!(near(200.0, 201.0, 70.0).y > 0.0);
+        assert!(near(200.0, 259.0, 70.0).y < 0.0);
+        assert!(near(200.0, 230.0, 51.0).z > 0.0);
+        assert!(near(200.0, 230.0, 89.0).z < 0.0);
+    }
+    
+    #[test]
+    fn test_constant_velocity_trajectory_is_evenly_spaced() {
+        let position = Vector3D::new(1.0, 2.0, 3.0);
+        let velocity = Vector3D::new(4.0, -2.0, 1.0);
+        let trajectory = predict_trajectory(&position, &velocity, &Vector3D::zero(), 0.5, 5, 50.0);
+        
+        assert_eq!(trajectory.len(), 5);
+        for (i, point) in trajectory.iter().enumerate() {
+            let expected = position.add(&velocity.multiply(0.5 * (i + 1) as f64));
+            assert!(point.distance_to(&expected) < 1e-9);
+        }
+    }
+    
+    #[test]
+    fn test_edge_speed_boost() {
+        let params = FlockingParams {
+            edge_speed_boost: 0.5,
+            enable_separation: false,
+            enable_alignment: false,
+            enable_cohesion: false,
+            ..FlockingParams::default()
+        };
+        // Nine drones clustered at the origin and one straggler, all flying
+        // at the same speed with no steering acting on them
+        let swarm: Vec<DroneState> = (0..10)
+            .map(|i| {
+                let x = if i == 9 { 150.0 } else { i as f64 };
+                drone(&format!("drone_{}", i), (x, 0.0, 50.0), (0.0, 45.0, 0.0))
+            })
+            .collect();
+        let speed = |state: &DroneState| Vector3D::from(state.velocity.clone()).magnitude();
+        
+        let stepped = step_swarm(&swarm, &params, &StepParams::default(), 0.1);
+        assert!(speed(&stepped[9]) > speed(&stepped[0]));
+        assert!((speed(&stepped[9]) - 45.0).abs() < 1e-9);
+        assert!(speed(&stepped[0]) < params.max_speed * 0.7);
+        
+        // Off in a wrap-around world, where the centroid means nothing
+        let toroidal = FlockingParams { topology: Topology::Toroidal { size: (1000.0, 1000.0, 0.0) }, ..params };
+        let stepped = step_swarm(&swarm, &toroidal, &StepParams::default(), 0.1);
+        assert!(stepped.iter().all(|state| (speed(state) - 45.0).abs() < 1e-9));
+    }
+    
+    #[test]
+    fn test_disabled_alignment_matches_zero_weight() {
+        let neighbors = vec![drone("drone_2", (20.0, 10.0, 0.0), (0.0, 3.0, 0.0))];
+        let drone = drone("drone_1", (0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        let disabled = FlockingParams { enable_alignment: false, ..FlockingParams::default() };
+        let zero_weight = FlockingParams { alignment_weight: 0.0, ..FlockingParams::default() };
+        
+        let a = calculate_boids_forces(&drone, &neighbors, &disabled);
+        let b = calculate_boids_forces(&drone, &neighbors, &zero_weight);
+        assert!(a.distance_to(&b) < 1e-12);
+    }
+    
+    #[test]
+    fn test_sum_limited_separation_in_dense_cluster() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let cluster: Vec<DroneState> = (0..8)
+            .map(|i| drone(&format!("drone_{}", i), (0.5, 0.1 * i as f64, 0.0), (0.0, 0.0, 0.0)))
+            .collect();
+        let neighbors: Vec<&DroneState> = cluster.iter().collect();
+        
+        let average = FlockingParams::default();
+        let summed = FlockingParams {
+            separation_aggregation: SeparationAggregation::SumLimited,
+            ..FlockingParams::default()
+        };
+        let average_force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &average);
+        let summed_force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &summed);
+        
+        assert!(summed_force.magnitude() > average_force.magnitude());
+        assert!(summed_force.magnitude() <= summed.max_force + 1e-9);
+        assert!(summed_force.x < 0.0);
+    }
+    
+    #[test]
+    fn test_max_contribution_separation_is_unit_scaled() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let cluster: Vec<DroneState> = (0..3)
+            .map(|i| drone(&format!("drone_{}", i), (2.0 + 3.0 * i as f64, 1.0, 0.0), (0.0, 0.0, 0.0)))
+            .collect();
+        let neighbors: Vec<&DroneState> = cluster.iter().collect();
+        
+        let average = FlockingParams::default();
+        let strongest = FlockingParams {
+            separation_aggregation: SeparationAggregation::MaxContribution,
+            ..FlockingParams::default()
+        };
+        let average_force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &average);
+        let strongest_force = calculate_separation(&position, &Vector3D::zero(), 0, &neighbors, &[], &strongest);
+        
+        assert!((average_force.magnitude() - 1.0).abs() < 1e-9);
+        assert!((strongest_force.magnitude() - 1.0).abs() < 1e-9);
+        assert!(strongest_force.x < 0.0);
+    }
+    
+    #[test]
+    fn test_simulate_is_deterministic() {
+        let mut initial = spread_swarm(15.0);
+        initial.push(DroneState { id: "stacked".to_string(), ..initial[0].clone() });
+        let params = FlockingParams::default();
+        let obstacles = [(30.0, 30.0, 10.0, 5.0)];
+        
+        let run = || simulate(&initial, &params, &obstacles, (400.0, 400.0, 200.0), 0.1, 50, 7);
+        let first = bincode::serialize(&run()).unwrap();
+        let second = bincode::serialize(&run()).unwrap();
+        assert_eq!(first, second);
+        
+        let final_states = run();
+        assert_eq!(final_states.len(), initial.len());
+        assert_eq!(final_states[0].timestamp, initial[0].timestamp + 50 * 100);
+    }
+    
+    #[test]
+    fn test_autotune_fixes_diverging_weights() {
+        let sample = spread_swarm(10.0);
+        let diverging = FlockingParams {
+            separation_weight: 10.0,
+            cohesion_weight: 0.0,
+            separation_full_radius: true,
+            ..FlockingParams::default()
+        };
+        let spread_after = |params: &FlockingParams| {
+            let mut states = sample.clone();
+            for _ in 0..STABILITY_STEPS {
+                states = step_swarm(&states, params, &StepParams::default(), STABILITY_DT);
+            }
+            swarm_spread(&states)
+        };
+        assert!(spread_after(&diverging) > swarm_spread(&sample));
+        
+        let tuned = autotune_weights(&sample, &diverging, 30);
+        assert!(stability_score(&sample, &tuned) > stability_score(&sample, &diverging));
+        assert!(spread_after(&tuned) <= swarm_spread(&sample) + 1e-9);
+    }
+    
+    #[test]
+    fn test_enclosing_sphere_of_points_on_sphere() {
+        let center = Vector3D::new(10.0, -5.0, 40.0);
+        let radius = 25.0;
+        let mut rng = StdRng::seed_from_u64(3);
+        let states: Vec<DroneState> = (0..200)
+            .map(|i| {
+                let direction = Vector3D::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize();
+                let point = center.add(&direction.multiply(radius));
+                drone(&format!("drone_{}", i), (point.x, point.y, point.z), (0.0, 0.0, 0.0))
+            })
+            .collect();
+        
+        let (found_center, found_radius) = enclosing_sphere(&states);
+        assert!((found_radius - radius).abs() < 0.5, "radius {}", found_radius);
+        assert!(found_center.distance_to(&center) < 0.5);
+        for state in &states {
+            let p = Vector3D::from(state.position.clone());
+            assert!(p.distance_to(&found_center) <= found_radius + 1e-6);
+        }
+    }
+    
+    #[test]
+    fn test_avoidance_priority_overrides_flocking() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let velocity = Vector3D::new(1.0, 0.0, 0.0);
+        let flocking_force = Vector3D::new(0.0, 5.0, 0.0);
+        let obstacles = [(4.0, 0.0, 0.0, 2.0)];
+        let params = FlockingParams::default();
+        let priority = AvoidanceParams { avoidance_priority: true, ..AvoidanceParams::default() };
+        
+        let pure = calculate_obstacle_avoidance(&position, &velocity, &obstacles, &priority)
+            .multiply(params.obstacle_avoidance_weight)
+            .limit(params.max_force);
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &obstacles, &params, &priority);
+        assert!(force.distance_to(&pure) < 1e-12);
+        
+        let blended = combine_with_avoidance(
+            &flocking_force,
+            &position,
+            &velocity,
+            &obstacles,
+            &params,
+            &AvoidanceParams::default(),
+        );
+        assert!(blended.y > 0.0);
+        
+        // Critical but outside the avoidance band: nothing to override with
+        let wide_critical = AvoidanceParams { critical_distance: 30.0, ..priority };
+        let far = [(27.0, 0.0, 0.0, 2.0)];
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &far, &params, &wide_critical);
+        assert!(force.distance_to(&flocking_force) < 1e-12);
+    }
+    
+    #[test]
+    fn test_critical_avoidance_with_obstacles_as_neighbors() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let velocity = Vector3D::new(1.0, 0.0, 0.0);
+        let flocking_force = Vector3D::new(0.0, 5.0, 0.0);
+        let obstacles = [(4.0, 0.0, 0.0, 2.0)];
+        let params = FlockingParams { obstacles_as_neighbors: true, ..FlockingParams::default() };
+        let priority = AvoidanceParams { avoidance_priority: true, ..AvoidanceParams::default() };
+        
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &obstacles, &params, &priority);
+        assert!(force.magnitude() > 0.0);
+        assert!(force.x < 0.0);
+        
+        // Outside the critical distance separation still carries the obstacle
+        let far = [(14.0, 0.0, 0.0, 2.0)];
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &far, &params, &priority);
+        assert!(force.distance_to(&flocking_force) < 1e-12);
+    }
+    
+    #[test]
+    fn test_critical_check_ignores_obstacles_behind_when_forward_only() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let velocity = Vector3D::new(1.0, 0.0, 0.0);
+        let flocking_force = Vector3D::new(0.0, 5.0, 0.0);
+        let behind = [(-4.0, 0.0, 0.0, 2.0)];
+        let ahead = [(14.0, 0.0, 0.0, 2.0), (-4.0, 0.0, 0.0, 2.0)];
+        let params = FlockingParams::default();
+        let avoidance = AvoidanceParams {
+            avoidance_priority: true,
+            forward_only: true,
+            ..AvoidanceParams::default()
+        };
+        
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &behind, &params, &avoidance);
+        assert!(force.distance_to(&flocking_force) < 1e-12);
+        
+        // The obstacle behind doesn't make avoiding the one ahead drop flocking
+        let force = combine_with_avoidance(&flocking_force, &position, &velocity, &ahead, &params, &avoidance);
+        assert!(force.x < 0.0 && force.y > 0.0);
+    }
+    
+    #[test]
+    fn test_distance_matrix_symmetric() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let states: Vec<DroneState> = (0..12)
+            .map(|i| {
+                let position = (rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0), rng.gen_range(0.0..30.0));
+                drone(&format!("drone_{}", i), position, (0.0, 0.0, 0.0))
+            })
+            .collect();
+        
+        let matrix = distance_matrix(&states);
+        assert_eq!(matrix.len(), states.len());
+        for (i, row) in matrix.iter().enumerate() {
+            assert_eq!(row[i], 0.0);
+            for (j, distance) in row.iter().enumerate() {
+                assert_eq!(*distance, matrix[j][i]);
+            }
+        }
+        let expected = Vector3D::from(states[2].position.clone())
+            .distance_to(&Vector3D::from(states[7].position.clone()));
+        assert_eq!(matrix[2][7], expected);
+    }
+    
+    #[test]
+    fn test_obstacles_as_neighbors_repel_by_surface_distance() {
+        let drone = drone("drone_1", (0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        let params = FlockingParams {
+            obstacles_as_neighbors: true,
+            separation_aggregation: SeparationAggregation::SumLimited,
+            enable_alignment: false,
+            enable_cohesion: false,
+            ..FlockingParams::default()
+        };
+        let small = [(10.0, 0.0, 0.0, 1.0)];
+        let large = [(10.0, 0.0, 0.0, 8.0)];
+        
+        let small_force = calculate_boids_forces_with_obstacles(&drone, &[], &small, &params);
+        let large_force = calculate_boids_forces_with_obstacles(&drone, &[], &large, &params);
+        assert!(small_force.x < 0.0);
+        assert!((small_force.x + params.separation_weight / 9.0).abs() < 1e-9);
+        assert!((large_force.x + params.separation_weight / 2.0).abs() < 1e-9);
+        
+        let disabled = FlockingParams { obstacles_as_neighbors: false, ..params };
+        assert_eq!(calculate_boids_forces_with_obstacles(&drone, &[], &large, &disabled).magnitude(), 0.0);
+    }
+    
+    #[test]
+    fn test_angular_momentum_of_milling_ring() {
+        let ring = |direction: f64| -> Vec<DroneState> {
+            (0..8)
+                .map(|i| {
+                    let angle = i as f64 * std::f64::consts::PI / 4.0;
+                    drone(
+                        &format!("drone_{}", i),
+                        (20.0 * angle.cos(), 20.0 * angle.sin(), 10.0),
+                        (-direction * 5.0 * angle.sin(), direction * 5.0 * angle.cos(), 0.0),
+                    )
+                })
+                .collect()
+        };
+        
+        let counter_clockwise = angular_momentum(&ring(1.0));
+        assert!((counter_clockwise.z - 8.0 * 20.0 * 5.0).abs() < 1e-9);
+        assert!(counter_clockwise.x.abs() < 1e-9 && counter_clockwise.y.abs() < 1e-9);
+        assert!(angular_momentum(&ring(-1.0)).z < 0.0);
+        
+        // A flock translating together has no spin
+        let mut translating = ring(1.0);
+        for state in &mut translating {
+            state.velocity = DroneVelocity { vx: 5.0, vy: 0.0, vz: 0.0 };
+        }
+        assert!(angular_momentum(&translating).magnitude() < 1e-9);
+    }
+    
+    #[test]
+    fn test_capsule_repels_from_nearest_segment_point() {
+        let beam = Capsule { start: (-50.0, 0.0, 10.0), end: (50.0, 0.0, 10.0), radius: 1.0 };
+        let position = Vector3D::new(5.0, 8.0, 10.0);
+        let velocity = Vector3D::zero();
+        
+        assert!(beam.closest_point(&position).distance_to(&Vector3D::new(5.0, 0.0, 10.0)) < 1e-12);
+        
+        let force = calculate_capsule_avoidance(&position, &velocity, &[beam], &AvoidanceParams::default());
+        assert!(force.y > 0.0);
+        assert!(force.x.abs() < 1e-12);
+        assert!(force.z.abs() < 1e-12);
+        
+        // Past the end cap the endpoint is the closest point
+        let far = Capsule { start: (-50.0, 0.0, 10.0), end: (0.0, 0.0, 10.0), radius: 1.0 };
+        assert!(far.closest_point(&position).distance_to(&Vector3D::new(0.0, 0.0, 10.0)) < 1e-12);
+    }
+    
+    #[test]
+    fn test_swarm_hull_2d_keeps_only_corners() {
+        let states: Vec<DroneState> = [
+            (0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0),
+            (5.0, 5.0), (2.0, 7.0), (5.0, 0.0),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y))| drone(&format!("drone_{}", i), (x, y, 3.0 * i as f64), (0.0, 0.0, 0.0)))
+        .collect();
+        
+        let hull = swarm_hull_2d(&states);
+        assert_eq!(hull, vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+    }
+    
+    #[test]
+    fn test_path_clear_between_obstacles() {
+        let from = Vector3D::new(0.0, 0.0, 10.0);
+        let to = Vector3D::new(100.0, 0.0, 10.0);
+        let gate = [(50.0, 12.0, 10.0, 8.0), (50.0, -12.0, 10.0, 8.0)];
+        
+        assert!(path_clear(&from, &to, &gate, 2.0));
+        assert!(!path_clear(&from, &to, &gate, 5.0));
+        
+        let blocked = [(50.0, 3.0, 10.0, 5.0)];
+        assert!(!path_clear(&from, &to, &blocked, 0.0));
+        
+        // Obstacles beyond the endpoints don't block
+        let behind = [(-20.0, 0.0, 10.0, 5.0)];
+        assert!(path_clear(&from, &to, &behind, 1.0));
+    }
+    
+    #[test]
+    fn test_rk4_tracks_ballistic_trajectory() {
+        let gravity = Vector3D::new(0.0, 0.0, -9.81);
+        let launch = Vector3D::new(12.0, 0.0, 20.0);
+        let (dt, steps) = (0.25, 12);
+        let t = dt * steps as f64;
+        let exact = launch.multiply(t).add(&gravity.multiply(0.5 * t * t));
+        
+        let mut euler = (Vector3D::zero(), launch.clone());
+        let mut rk4 = (Vector3D::zero(), launch.clone());
+        for _ in 0..steps {
+            euler = IntegrationMethod::Euler.integrate(&euler.0, &euler.1, &gravity, dt, 100.0);
+            rk4 = IntegrationMethod::Rk4.integrate(&rk4.0, &rk4.1, &gravity, dt, 100.0);
+        }
+        
+        let euler_error = euler.0.distance_to(&exact);
+        let rk4_error = rk4.0.distance_to(&exact);
+        assert!(rk4_error < euler_error);
+        assert!(rk4_error < 1e-9);
+    }
+    
+    #[test]
+    fn test_nearest_neighbor_distances() {
+        let make = |id: &str, x: f64, y: f64| drone(id, (x, y, 0.0), (0.0, 0.0, 0.0));
+        let states = vec![make("a", 0.0, 0.0), make("b", 3.0, 4.0), make("c", 20.0, 4.0)];
+        assert_eq!(nearest_neighbor_distances(&states), vec![5.0, 5.0, 17.0]);
+        
+        // Grid search agrees with brute force on a larger random swarm
+        let mut rng = StdRng::seed_from_u64(5);
+        let swarm: Vec<DroneState> = (0..60)
+            .map(|i| make(&format!("drone_{}", i), rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0)))
+            .collect();
+        let matrix = distance_matrix(&swarm);
+        let brute: Vec<f64> = matrix
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, d)| *d)
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect();
+        assert_eq!(nearest_neighbor_distances(&swarm), brute);
+        assert!(nearest_neighbor_distances(&states[..1]).is_empty());
+    }
+    
+    #[test]
+    fn test_drone_radius_widens_avoidance() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let velocity = Vector3D::new(1.0, 0.0, 0.0);
+        // Obstacle surface is 22 units away, just outside the default 20
+        let obstacles = [(25.0, 0.0, 0.0, 3.0)];
+        
+        let point = AvoidanceParams::default();
+        let sized = AvoidanceParams { drone_radius: 4.0, ..AvoidanceParams::default() };
+        
+        assert_eq!(calculate_obstacle_avoidance(&position, &velocity, &obstacles, &point).magnitude(), 0.0);
+        assert!(calculate_obstacle_avoidance(&position, &velocity, &obstacles, &sized).x < 0.0);
+    }
+    
+    #[test]
+    fn test_max_delta_v_clamps_velocity_change() {
+        let velocity = Vector3D::new(2.0, 0.0, 0.0);
+        let acceleration = Vector3D::new(0.0, 40.0, 0.0);
+        let clamped = IntegrationParams { max_delta_v: 3.0, ..IntegrationParams::default() };
+        
+        let (_, unlimited) = IntegrationParams::default().integrate(&Vector3D::zero(), &velocity, &acceleration, 2.0, 100.0);
+        let (_, limited) = clamped.integrate(&Vector3D::zero(), &velocity, &acceleration, 2.0, 100.0);
+        
+        assert!((unlimited.subtract(&velocity).magnitude() - 80.0).abs() < 1e-9);
+        assert!((limited.subtract(&velocity).magnitude() - 3.0).abs() < 1e-9);
+        
+        // Gravity shares the same budget
+        let heavy = IntegrationParams { gravity_vector: (0.0, 0.0, -9.81), ..clamped };
+        let (_, falling) = heavy.integrate(&Vector3D::zero(), &velocity, &acceleration, 2.0, 100.0);
+        let (_, dropped) = heavy.integrate(&Vector3D::zero(), &velocity, &Vector3D::zero(), 2.0, 100.0);
+        assert!((falling.subtract(&velocity).magnitude() - 3.0).abs() < 1e-9);
+        assert!((dropped.subtract(&velocity).magnitude() - 3.0).abs() < 1e-9);
+        assert!(dropped.z < 0.0);
+    }
+    
+    #[test]
+    fn test_horizontal_gravity_pushes_sideways() {
+        let sloped = IntegrationParams { gravity_vector: (2.0, 0.0, 0.0), ..IntegrationParams::default() };
+        let (position, velocity) = sloped.integrate(&Vector3D::zero(), &Vector3D::zero(), &Vector3D::zero(), 0.5, 100.0);
+        
+        assert!((velocity.x - 1.0).abs() < 1e-12);
+        assert!(position.x > 0.0);
+        assert_eq!((velocity.y, velocity.z), (0.0, 0.0));
+    }
+    
+    #[test]
+    fn test_separation_from_pairs_matches_separation() {
+        let position = Vector3D::new(0.0, 0.0, 50.0);
+        let neighbors = spread_swarm(8.0);
+        let refs: Vec<&DroneState> = neighbors.iter().collect();
+        let pairs: Vec<(Vector3D, f64)> = neighbors
+            .iter()
+            .map(|n| {
+                let neighbor_pos = Vector3D::from(n.position.clone());
+                let distance = neighbor_pos.distance_to(&position);
+                (neighbor_pos, distance)
+            })
+            .collect();
+        
+        for aggregation in [
+            SeparationAggregation::AverageNormalized,
+            SeparationAggregation::SumLimited,
+            SeparationAggregation::MaxContribution,
+        ] {
+            let params = FlockingParams { separation_aggregation: aggregation, ..FlockingParams::default() };
+            let expected = calculate_separation(&position, &Vector3D::zero(), 0, &refs, &[], &params);
+            let from_pairs = separation_from_pairs(&position, &pairs, &params);
+            assert!(from_pairs.distance_to(&expected) < 1e-12);
+        }
+    }
+    
+    #[test]
+    fn test_alignment_modes_diverge() {
+        let velocity = Vector3D::new(40.0, 0.0, 0.0);
+        let neighbors = [
+            drone("north", (5.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            drone("northeast", (-5.0, 0.0, 0.0), (1.0, 1.0, 0.0)),
+        ];
+        let refs: Vec<&DroneState> = neighbors.iter().collect();
+        
+        let steer = calculate_alignment(&velocity, &refs, &FlockingParams::default());
+        let direction_only = FlockingParams { alignment_mode: AlignmentMode::DirectionOnly, ..FlockingParams::default() };
+        let direction = calculate_alignment(&velocity, &refs, &direction_only);
+        
+        // Steering subtracts the drone's own +x heading; direction-only does not
+        assert!(steer.x < 0.0);
+        assert!(direction.x > 0.0);
+        assert!((direction.magnitude() - 1.0).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_simplify_trajectory_line_and_corner() {
+        let line: Vec<Vector3D> = (0..=10).map(|i| Vector3D::new(i as f64, 2.0 * i as f64, 0.0)).collect();
+        let simplified = simplify_trajectory(&line, 0.01);
+        assert_eq!(simplified.len(), 2);
+        assert_eq!(simplified[1].x, 10.0);
+        
+        // Along x, then a sharp turn up z
+        let corner: Vec<Vector3D> = (0..=5)
+            .map(|i| Vector3D::new(i as f64, 0.0, 0.0))
+            .chain((1..=5).map(|i| Vector3D::new(5.0, 0.0, i as f64)))
+            .collect();
+        let simplified = simplify_trajectory(&corner, 0.01);
+        assert_eq!(simplified.len(), 3);
+        assert_eq!((simplified[1].x, simplified[1].z), (5.0, 0.0));
+    }
+    
+    #[test]
+    fn test_oscillation_risk_separates_stiff_and_damped() {
+        let stiff = FlockingParams {
+            neighbor_radius: 100.0,
+            separation_radius: 95.0,
+            separation_weight: 5.0,
+            cohesion_weight: 5.0,
+            alignment_weight: 0.0,
+            max_force: 50.0,
+            max_speed: 10.0,
+            ..FlockingParams::default()
+        };
+        let damped = FlockingParams {
+            neighbor_radius: 100.0,
+            separation_radius: 20.0,
+            separation_weight: 1.0,
+            cohesion_weight: 1.0,
+            alignment_weight: 2.0,
+            max_force: 2.0,
+            max_speed: 20.0,
+            ..FlockingParams::default()
+        };
+        
+        assert!(stiff.oscillation_risk() > 0.9);
+        assert!(damped.oscillation_risk() < 0.05);
+        
+        let no_cohesion = FlockingParams { enable_cohesion: false, ..stiff };
+        assert_eq!(no_cohesion.oscillation_risk(), 0.0);
+    }
+    
+    #[test]
+    fn test_curl_force_is_tangent_and_rotates() {
+        let center = Vector3D::new(10.0, 10.0, 50.0);
+        let axis = Vector3D::new(0.0, 0.0, 2.0);
+        for angle in [0.0f64, 1.0, 2.5, 4.0] {
+            let offset = Vector3D::new(5.0 * angle.cos(), 5.0 * angle.sin(), 3.0);
+            let force = curl_force(&center.add(&offset), &center, &axis, 2.0);
+            
+            assert!((force.magnitude() - 2.0).abs() < 1e-9);
+            assert!(force.dot(&offset).abs() < 1e-9);
+            // Counter-clockwise seen from +z
+            assert!(offset.cross(&force).z > 0.0);
+        }
+        assert_eq!(curl_force(&center, &center, &axis, 2.0).magnitude(), 0.0);
+        
+        // In the batch step the whole ring starts turning the same way
+        let params = FlockingParams { enable_alignment: false, enable_cohesion: false, enable_separation: false, ..FlockingParams::default() };
+        let step_params = StepParams { curl_center: Some((10.0, 5.0, 50.0)), ..StepParams::default() };
+        let stepped = step_swarm(&spread_swarm(10.0), &params, &step_params, 0.1);
+        let swirl = angular_momentum(&stepped);
+        assert!(swirl.z > 0.0);
+        
+        // A strong vortex still respects max_force
+        let strong = StepParams { curl_weight: 100.0, ..step_params };
+        let dt = 0.1;
+        for drone in step_swarm(&spread_swarm(10.0), &params, &strong, dt) {
+            let delta_v = Vector3D::from(drone.velocity).magnitude();
+            assert!(delta_v <= params.max_force * dt + 1e-9);
+        }
+    }
+    
+    #[test]
+    fn test_weight_override_applies_to_one_drone() {
+        let params = FlockingParams { enable_separation: false, enable_alignment: false, ..FlockingParams::default() };
+        let scout = DroneState {
+            weight_overrides: Some(FlockingWeights {
+                separation_weight: 2.0,
+                alignment_weight: 1.0,
+                cohesion_weight: 0.0,
+                obstacle_avoidance_weight: 3.0,
+            }),
+            ..drone("scout", (0.0, 0.0, 50.0), (0.0, 0.0, 0.0))
+        };
+        let core = drone("core", (30.0, 0.0, 50.0), (0.0, 0.0, 0.0));
+        
+        let scout_force = calculate_boids_forces(&scout, std::slice::from_ref(&core), &params);
+        let core_force = calculate_boids_forces(&core, std::slice::from_ref(&scout), &params);
+        assert_eq!(scout_force.magnitude(), 0.0);
+        assert!(core_force.x < 0.0);
+    }
+    
+    #[test]
+    fn test_avoidance_hysteresis_latches_until_exit() {
+        let obstacles = [(0.0, 0.0, 10.0, 5.0)];
+        let hysteresis = AvoidanceHysteresis { enter_distance: 10.0, exit_distance: 20.0 };
+        let params = AvoidanceParams::default();
+        let velocity = Vector3D::zero();
+        let mut active = Vec::new();
+        
+        // Clearances around the enter boundary, then clearly past exit
+        let mut forces = Vec::new();
+        for clearance in [10.5, 9.5, 10.5, 11.0, 10.5, 21.0] {
+            let position = Vector3D::new(5.0 + clearance, 0.0, 10.0);
+            let force = calculate_obstacle_avoidance_with_hysteresis(
+                &position, &velocity, &obstacles, &params, &hysteresis, &mut active,
+            );
+            forces.push(force.x);
+        }
+        
+        assert_eq!(forces[0], 0.0);
+        assert!(forces[1..5].iter().all(|&x| x > 0.0));
+        assert_eq!(forces[5], 0.0);
+        assert_eq!(active, vec![false]);
+    }
+    
+    #[test]
+    fn test_order_parameter_aligned_and_opposed() {
+        let with_velocity = |velocities: &[(f64, f64)]| -> Vec<DroneState> {
+            velocities
+                .iter()
+                .enumerate()
+                .map(|(i, &(vx, vy))| {
+                    drone(&format!("drone_{}", i), (i as f64 * 10.0, 0.0, 50.0), (vx, vy, 0.0))
+                })
+                .collect()
+        };
+        
+        let aligned = with_velocity(&[(1.0, 1.0), (5.0, 5.0), (0.2, 0.2)]);
+        assert!((order_parameter(&aligned) - 1.0).abs() < 1e-12);
+        
+        let opposed = with_velocity(&[(3.0, 0.0), (-7.0, 0.0)]);
+        assert!(order_parameter(&opposed) < 1e-12);
+    }
+    
+    #[test]
+    fn test_closing_neighbor_separates_harder() {
+        let params = FlockingParams { closing_speed_weight: 0.5, ..FlockingParams::default() };
+        let position = Vector3D::new(0.0, 0.0, 50.0);
+        let velocity = Vector3D::zero();
+        let neighbor = |vx: f64| drone("neighbor", (10.0, 0.0, 50.0), (vx, 0.0, 0.0));
+        let (approaching, receding) = (neighbor(-4.0), neighbor(4.0));
+        
+        let toward = calculate_separation(&position, &velocity, 0, &[&approaching], &[], &params);
+        let away = calculate_separation(&position, &velocity, 0, &[&receding], &[], &params);
+        assert!(toward.x < 0.0 && away.x < 0.0);
+        assert!(toward.magnitude() > away.magnitude());
+        assert!((away.magnitude() - 1.0).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_spawn_lattice_grid_is_square() {
+        let grid = spawn_lattice(9, 5.0, (10.0, 20.0, 30.0), "grid").unwrap();
+        let positions: Vec<(f64, f64, f64)> = grid.iter().map(|s| (s.position.x, s.position.y, s.position.z)).collect();
+        
+        let mut expected = Vec::new();
+        for row in 0..3 {
+            for column in 0..3 {
+                expected.push((10.0 + 5.0 * column as f64, 20.0 + 5.0 * row as f64, 30.0));
+            }
+        }
+        assert_eq!(positions, expected);
+        assert_eq!(grid[8].id, "drone_8");
+        assert!(grid.iter().all(|s| Vector3D::from(s.velocity.clone()).magnitude() == 0.0));
+        
+        let cube = spawn_lattice(8, 1.0, (0.0, 0.0, 0.0), "cube").unwrap();
+        assert_eq!((cube[7].position.x, cube[7].position.y, cube[7].position.z), (1.0, 1.0, 1.0));
+        assert!(spawn_lattice(4, 1.0, (0.0, 0.0, 0.0), "spiral").is_none());
+    }
+    
+    #[test]
+    fn test_turn_rate_limits_reversal() {
+        let rate = 0.3;
+        let limited = IntegrationParams { max_turn_rate_rad: rate, ..IntegrationParams::default() };
+        let velocity = Vector3D::new(10.0, 0.0, 0.0);
+        let reverse = Vector3D::new(-200.0, 0.0, 0.0);
+        
+        let (_, free) = IntegrationParams::default().integrate(&Vector3D::zero(), &velocity, &reverse, 0.1, 50.0);
+        assert!(free.x < 0.0);
+        
+        let (position, turned) = limited.integrate(&Vector3D::zero(), &velocity, &reverse, 0.1, 50.0);
+        let angle = velocity.normalize().dot(&turned.normalize()).acos();
+        assert!(angle <= rate + 1e-9);
+        assert!((angle - rate).abs() < 1e-9);
+        assert!(position.subtract(&turned.multiply(0.1)).magnitude() < 1e-12);
+        
+        // Gentle turns pass through untouched
+        let nudge = Vector3D::new(0.0, 5.0, 0.0);
+        let (_, a) = limited.integrate(&Vector3D::zero(), &velocity, &nudge, 0.1, 50.0);
+        let (_, b) = IntegrationParams::default().integrate(&Vector3D::zero(), &velocity, &nudge, 0.1, 50.0);
+        assert!(a.distance_to(&b) < 1e-12);
+    }
+    
+    #[test]
+    fn test_forward_clearance_ahead_and_aside() {
+        let position = Vector3D::new(0.0, 0.0, 10.0);
+        let velocity = Vector3D::new(3.0, 0.0, 0.0);
+        
+        let ahead = [(25.0, 0.0, 10.0, 5.0)];
+        assert!((forward_clearance(&position, &velocity, &ahead, 100.0) - 20.0).abs() < 1e-9);
+        
+        let aside = [(25.0, 12.0, 10.0, 5.0)];
+        assert_eq!(forward_clearance(&position, &velocity, &aside, 100.0), 100.0);
+        
+        let behind = [(-25.0, 0.0, 10.0, 5.0)];
+        assert_eq!(forward_clearance(&position, &velocity, &behind, 100.0), 100.0);
+    }
+    
+    #[test]
+    fn test_cohesion_dead_radius() {
+        let params = FlockingParams { cohesion_dead_radius: 3.0, ..FlockingParams::default() };
+        let neighbors: Vec<DroneState> = [(-10.0, 0.0), (10.0, 0.0)]
+            .iter()
+            .map(|&(x, y)| drone(&format!("drone_{}", x), (x, y, 50.0), (0.0, 0.0, 0.0)))
+            .collect();
+        let refs: Vec<&DroneState> = neighbors.iter().collect();
+        
+        let near_center = calculate_cohesion(&Vector3D::new(2.0, 0.0, 50.0), &refs, &params);
+        assert_eq!(near_center.magnitude(), 0.0);
+        
+        let outside = calculate_cohesion(&Vector3D::new(5.0, 0.0, 50.0), &refs, &params);
+        let normal = calculate_cohesion(&Vector3D::new(5.0, 0.0, 50.0), &refs, &FlockingParams::default());
+        assert!(outside.x < 0.0);
+        assert!(outside.distance_to(&normal) < 1e-12);
+    }
+    
+    #[test]
+    fn test_weighted_centroid_shifts_toward_heavier_point() {
+        let points = [Vector3D::new(0.0, 0.0, 0.0), Vector3D::new(12.0, 0.0, 6.0)];
+        
+        let even = weighted_centroid(&points, &[1.0, 1.0]).unwrap();
+        assert!(even.distance_to(&Vector3D::new(6.0, 0.0, 3.0)) < 1e-12);
+        
+        // Doubling the second point's weight moves the centroid to 2/3 of the way
+        let doubled = weighted_centroid(&points, &[1.0, 2.0]).unwrap();
+        assert!(doubled.distance_to(&Vector3D::new(8.0, 0.0, 4.0)) < 1e-12);
+        
+        assert!(weighted_centroid(&points, &[1.0]).is_none());
+        assert!(weighted_centroid(&points, &[0.0, 0.0]).is_none());
+    }
+    
+    #[test]
+    fn test_enforce_min_spacing_splits_coincident_drones() {
+        let drone = drone("a", (3.0, 4.0, 50.0), (1.0, 0.0, 0.0));
+        let states = vec![drone.clone(), DroneState { id: "b".to_string(), ..drone }];
+        
+        let spaced = enforce_min_spacing(&states, 2.5, 1);
+        let a = Vector3D::from(spaced[0].position.clone());
+        let b = Vector3D::from(spaced[1].position.clone());
+        assert!(a.distance_to(&b) >= 2.5 - 1e-9);
+        assert!(a.add(&b).multiply(0.5).distance_to(&Vector3D::new(3.0, 4.0, 50.0)) < 1e-12);
+        assert_eq!(spaced[1].velocity.vx, 1.0);
+        
+        // Drones already far enough apart stay put
+        let spread = spread_swarm(10.0);
+        let untouched = enforce_min_spacing(&spread, 2.5, 3);
+        assert!(spread.iter().zip(&untouched).all(|(a, b)| a.position.x == b.position.x && a.position.y == b.position.y));
+    }
+    
+    #[test]
+    fn test_local_alignment_error_flags_contrarian() {
+        let mut states = spread_swarm(10.0);
+        for state in &mut states {
+            state.velocity = DroneVelocity { vx: 5.0, vy: 0.5, vz: 0.0 };
+        }
+        states[4].velocity = DroneVelocity { vx: -5.0, vy: 0.0, vz: 0.0 };
+        
+        let errors = local_alignment_errors(&states, 25.0);
+        assert_eq!(errors[4].0, "drone_4");
+        assert!(errors[4].1 > 3.0);
+        assert!(errors[0].1 < 0.1);
+    }
+    
+    #[test]
+    fn test_randomize_velocities_is_seeded_and_capped() {
+        let lattice = spawn_lattice(27, 10.0, (0.0, 0.0, 50.0), "cube").unwrap();
+        let speeds = |states: &[DroneState]| -> Vec<f64> {
+            states.iter().map(|s| Vector3D::from(s.velocity.clone()).magnitude()).collect()
+        };
+        
+        let first = randomize_velocities(&lattice, 8.0, 42);
+        let again = randomize_velocities(&lattice, 8.0, 42);
+        let other = randomize_velocities(&lattice, 8.0, 43);
+        assert_eq!(speeds(&first), speeds(&again));
+        assert_ne!(speeds(&first), speeds(&other));
+        assert!(speeds(&first).iter().all(|&speed| speed <= 8.0));
+        assert!(speeds(&first).iter().any(|&speed| speed > 0.0));
+        assert_eq!(first[3].position.x, lattice[3].position.x);
+    }
+    
+    #[test]
+    fn test_lower_priority_drone_yields() {
+        let params = FlockingParams { priority_yield: 0.5, ..FlockingParams::default() };
+        let low = DroneState { priority: 1, ..drone("low", (0.0, 0.0, 50.0), (0.0, 0.0, 0.0)) };
+        let high = DroneState { priority: 5, ..drone("high", (10.0, 0.0, 50.0), (0.0, 0.0, 0.0)) };
+        let separation = |drone: &DroneState, other: &DroneState, params: &FlockingParams| {
+            let position = Vector3D::from(drone.position.clone());
+            calculate_separation(&position, &Vector3D::zero(), drone.priority, &[other], &[], params)
+        };
+        
+        let yielding = separation(&low, &high, &params);
+        let holding = separation(&high, &low, &params);
+        assert!(yielding.x < 0.0 && holding.x > 0.0);
+        assert!(yielding.magnitude() > holding.magnitude());
+        
+        let symmetric = FlockingParams::default();
+        assert_eq!(separation(&low, &high, &symmetric).magnitude(), separation(&high, &low, &symmetric).magnitude());
+    }
+    
+    #[test]
+    fn test_expansion_rate_sign() {
+        let ring = |speed: f64| -> Vec<DroneState> {
+            [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)]
+                .iter()
+                .enumerate()
+                .map(|(i, &(dx, dy))| {
+                    drone(&format!("drone_{}", i), (10.0 * dx, 10.0 * dy, 50.0), (speed * dx, speed * dy, 0.0))
+                })
+                .collect()
+        };
+        
+        assert!((expansion_rate(&ring(2.0)) - 2.0).abs() < 1e-12);
+        assert!(expansion_rate(&ring(-3.0)) < 0.0);
+        assert_eq!(expansion_rate(&[]), 0.0);
+        
+        // A lopsided swarm flying rigidly along x neither spreads nor contracts
+        let translating: Vec<DroneState> = [(0.0, 0.0), (0.0, 2.0), (2.0, 0.0), (30.0, 0.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| drone(&format!("drone_{}", i), (x, y, 50.0), (5.0, 0.0, 0.0)))
+            .collect();
+        assert!(expansion_rate(&translating).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_intercept_point_leads_crossing_target() {
+        let pursuer = Vector3D::new(0.0, 0.0, 10.0);
+        let target = Vector3D::new(100.0, 0.0, 10.0);
+        let target_velocity = Vector3D::new(0.0, 10.0, 0.0);
+        
+        let meet = intercept_point(&pursuer, &target, &target_velocity, 20.0).unwrap();
+        assert!(meet.y > target.y);
+        assert!((meet.x - target.x).abs() < 1e-9);
+        // Both arrive at the same time
+        let time = meet.distance_to(&target) / 10.0;
+        assert!((pursuer.distance_to(&meet) / 20.0 - time).abs() < 1e-9);
+        
+        // A faster target running straight away can't be caught
+        let fleeing = Vector3D::new(30.0, 0.0, 0.0);
+        assert!(intercept_point(&pursuer, &target, &fleeing, 20.0).is_none());
+        assert!(intercept_point(&pursuer, &target, &target_velocity, 0.0).is_none());
+    }
+    
+    #[test]
+    fn test_min_jerk_trajectory_endpoints_at_rest() {
+        let start = Vector3D::new(0.0, 0.0, 10.0);
+        let goal = Vector3D::new(30.0, -20.0, 40.0);
+        let path = min_jerk_trajectory(&start, &goal, 5.0, 1000);
+        
+        assert_eq!(path.len(), 1001);
+        assert!(path[0].distance_to(&start) < 1e-12);
+        assert!(path[1000].distance_to(&goal) < 1e-12);
+        
+        // Finite-difference speed vanishes at both ends and peaks mid-move
+        let dt = 5.0 / 1000.0;
+        let speed = |k: usize| path[k + 1].distance_to(&path[k]) / dt;
+        assert!(speed(0) < 1e-4);
+        assert!(speed(999) < 1e-4);
+        assert!(speed(500) > speed(250));
+    }
+    
+    #[test]
+    fn test_line_of_sight_hides_occluded_neighbors() {
+        let params = FlockingParams {
+            line_of_sight: true,
+            enable_separation: false,
+            enable_alignment: false,
+            ..FlockingParams::default()
+        };
+        let drone_at = |id: &str, x: f64, y: f64| drone(id, (x, y, 50.0), (0.0, 0.0, 0.0));
+        let drone = drone_at("drone", 0.0, 0.0);
+        let hidden = drone_at("hidden", 40.0, 0.0);
+        let open = drone_at("open", 0.0, 40.0);
+        let obstacles = [(20.0, 0.0, 50.0, 5.0)];
+        
+        // Cohesion pulls only toward the visible neighbor
+        let force = calculate_boids_forces_with_obstacles(&drone, &[hidden.clone(), open.clone()], &obstacles, &params);
+        assert!(force.y > 0.0);
+        assert!(force.x.abs() < 1e-9);
+        
+        let blind = FlockingParams { line_of_sight: false, ..params };
+        let force = calculate_boids_forces_with_obstacles(&drone, &[hidden, open], &obstacles, &blind);
+        assert!(force.x > 0.0 && force.y > 0.0);
+    }
+    
+    #[test]
+    fn test_shape_descriptor_of_a_line() {
+        let line: Vec<DroneState> = (0..10)
+            .map(|i| drone(&format!("drone_{}", i), (i as f64 * 5.0, i as f64 * 5.0, 50.0), (0.0, 0.0, 0.0)))
+            .collect();
+        
+        let (major, middle, minor) = shape_descriptor(&line);
+        assert!(major > 100.0);
+        assert!(middle.abs() < 1e-9 && minor.abs() < 1e-9);
+        assert_eq!(shape_descriptor(&[]), (0.0, 0.0, 0.0));
+    }
+    
+    #[test]
+    fn test_sample_free_points_avoid_obstacles() {
+        let center = Vector3D::new(0.0, 0.0, 50.0);
+        let obstacles = [(0.0, 0.0, 50.0, 6.0), (8.0, 0.0, 50.0, 3.0)];
+        let points = sample_free_points(&center, 10.0, 50, &obstacles, 7);
+        
+        assert_eq!(points.len(), 50);
+        for point in &points {
+            assert!(point.distance_to(&center) <= 10.0 + 1e-9);
+            for &(ox, oy, oz, r) in &obstacles {
+                assert!(point.distance_to(&Vector3D::new(ox, oy, oz)) > r);
+            }
+        }
+        
+        let again = sample_free_points(&center, 10.0, 50, &obstacles, 7);
+        assert!(points.iter().zip(&again).all(|(a, b)| a.distance_to(b) == 0.0));
+        
+        // Nothing free when an obstacle swallows the whole ball
+        assert!(sample_free_points(&center, 10.0, 5, &[(0.0, 0.0, 50.0, 20.0)], 7).is_empty());
+    }
+    
+    #[test]
+    fn test_presets_are_distinct_and_valid() {
+        let presets: Vec<FlockingParams> = PRESET_NAMES
+            .iter()
+            .map(|name| FlockingParams::preset(name).unwrap())
+            .collect();
+        
+        for (i, preset) in presets.iter().enumerate() {
+            assert!(preset.validate().is_empty());
+            for other in &presets[i + 1..] {
+                assert_ne!(preset.fingerprint(), other.fingerprint());
+            }
+        }
+        assert!(FlockingParams::preset("no_such_preset").is_none());
+    }
+    
+    #[test]
+    fn test_sensor_overlap() {
+        let make = |x: f64, vx: f64| drone("drone", (x, 0.0, 10.0), (vx, 0.0, 0.0));
+        
+        assert!(sensor_overlap(&make(0.0, 1.0), &make(0.0, 3.0), 50.0, 60.0) > 0.99);
+        assert!(sensor_overlap(&make(0.0, 1.0), &make(0.0, -1.0), 50.0, 60.0) < 0.01);
+        
+        let partial = sensor_overlap(&make(0.0, 1.0), &make(20.0, 1.0), 50.0, 60.0);
+        assert!(partial > 0.05 && partial < 0.95);
+    }
+    
+    #[test]
+    fn test_quantize_vector() {
+        let force = Vector3D::new(1.26, -0.74, 0.1);
+        let snapped = force.quantize(0.5);
+        assert_eq!((snapped.x, snapped.y, snapped.z), (1.5, -0.5, 0.0));
+        
+        let unchanged = force.quantize(0.0);
+        assert_eq!((unchanged.x, unchanged.y, unchanged.z), (force.x, force.y, force.z));
+    }
+    
+    #[test]
+    fn test_lidar_wall_pushes_away() {
+        // Vertical wall 5 m to the +y side, plus a distant point that is ignored
+        let mut points: Vec<(f32, f32, f32)> = (-10..=10)
+            .flat_map(|x| (-3..=3).map(move |z| (x as f32, 5.0, z as f32)))
+            .collect();
+        points.push((0.0, -80.0, 0.0));
+        
+        let force = lidar_repulsion(&points, 20.0, 10.0);
+        assert!(force.y < 0.0);
+        assert!(force.x.abs() < 1e-9 && force.z.abs() < 1e-9);
+        assert!(force.magnitude() <= 10.0 + 1e-9);
+        assert_eq!(lidar_repulsion(&points, 1.0, 10.0).magnitude(), 0.0);
+    }
+    
+    #[test]
+    fn test_centroid_tracks_target() {
+        let params = FlockingParams::default();
+        let target = (150.0, -80.0, 60.0);
+        let guided = StepParams { centroid_target: Some(target), ..StepParams::default() };
+        let target = Vector3D::new(target.0, target.1, target.2);
+        
+        let mut free = spread_swarm(40.0);
+        let mut tracked = free.clone();
+        let start = swarm_centroid(&tracked).distance_to(&target);
+        for _ in 0..300 {
+            free = step_swarm(&free, &params, &StepParams::default(), 0.1);
+            tracked = step_swarm(&tracked, &params, &guided, 0.1);
+        }
+        
+        assert!(swarm_centroid(&tracked).distance_to(&target) < start * 0.05);
+        let (free_spread, tracked_spread) = (mean_pairwise_distance(&free), mean_pairwise_distance(&tracked));
+        assert!((free_spread - tracked_spread).abs() < free_spread * 0.2);
+        
+        // A heavy correction on top of flocking still respects max_force
+        let heavy = StepParams { centroid_weight: 50.0, ..guided };
+        let dt = 0.1;
+        for drone in step_swarm(&spread_swarm(40.0), &params, &heavy, dt) {
+            let delta_v = Vector3D::from(drone.velocity).magnitude();
+            assert!(delta_v <= params.max_force * dt + 1e-9);
+        }
+    }
+    
+    #[test]
+    fn test_params_fingerprint() {
+        let base = FlockingParams::default();
+        assert_eq!(base.fingerprint(), FlockingParams::default().fingerprint());
+        
+        let variants = [
+            FlockingParams { neighbor_radius: 101.0, ..base.clone() },
+            FlockingParams { separation_radius: 51.0, ..base.clone() },
+            FlockingParams { max_speed: 51.0, ..base.clone() },
+            FlockingParams { max_force: 11.0, ..base.clone() },
+            FlockingParams { separation_weight: 2.5, ..base.clone() },
+            FlockingParams { alignment_weight: 1.5, ..base.clone() },
+            FlockingParams { cohesion_weight: 1.5, ..base.clone() },
+            FlockingParams { obstacle_avoidance_weight: 3.5, ..base.clone() },
+            FlockingParams { separation_full_radius: true, ..base.clone() },
+            FlockingParams { topology: Topology::Toroidal { size: (100.0, 100.0, 0.0) }, ..base.clone() },
+            FlockingParams { separation_min_distance: 0.5, ..base.clone() },
+            FlockingParams { min_speed_for_alignment: 0.1, ..base.clone() },
+            FlockingParams { edge_speed_boost: 0.5, ..base.clone() },
+            FlockingParams { enable_separation: false, ..base.clone() },
+            FlockingParams { enable_alignment: false, ..base.clone() },
+            FlockingParams { enable_cohesion: false, ..base.clone() },
+            FlockingParams { separation_aggregation: SeparationAggregation::SumLimited, ..base.clone() },
+            FlockingParams { obstacles_as_neighbors: true, ..base.clone() },
+            FlockingParams { alignment_mode: AlignmentMode::DirectionOnly, ..base.clone() },
+            FlockingParams { closing_speed_weight: 0.5, ..base.clone() },
+            FlockingParams { cohesion_dead_radius: 2.0, ..base.clone() },
+            FlockingParams { priority_yield: 0.5, ..base.clone() },
+            FlockingParams { line_of_sight: true, ..base.clone() },
+            FlockingParams { trail_weight: 0.3, ..base.clone() },
+        ];
+        for variant in &variants {
+            assert_ne!(variant.fingerprint(), base.fingerprint());
+        }
+    }
+    
+    #[test]
+    fn test_toroidal_neighbors_across_edge() {
+        let make = |id: &str, x: f64| drone(id, (x, 0.0, 0.0), (0.0, 0.0, 0.0));
+        let drone = make("a", 48.0);
+        let neighbors = vec![make("b", -48.0)];
+        let bounded = FlockingParams {
+            neighbor_radius: 10.0,
+            separation_radius: 2.0,
+            ..FlockingParams::default()
+        };
+        let toroidal = FlockingParams {
+            topology: Topology::Toroidal { size: (100.0, 100.0, 0.0) },
+            ..bounded.clone()
+        };
+        
+        assert_eq!(calculate_boids_forces(&drone, &neighbors, &bounded).magnitude(), 0.0);
+        
+        // The neighbor is 4 units away across the +x edge, so cohesion pulls +x
+        let force = calculate_boids_forces(&drone, &neighbors, &toroidal);
+        assert!(force.x > 0.0);
+        
+        let (position, _) = integrate_motion_in_topology(
+            &Vector3D::new(49.0, 0.0, 0.0),
+            &Vector3D::new(4.0, 0.0, 0.0),
+            &Vector3D::zero(),
+            1.0,
+            50.0,
+            &toroidal.topology,
+            &IntegrationParams::default(),
+        );
+        assert!((position.x + 47.0).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_safe_velocity_deflects_only_collisions() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let neighbors = vec![drone("ahead", (10.0, 0.0, 0.0), (0.0, 0.0, 0.0))];
+        
+        let head_on = Vector3D::new(5.0, 0.0, 0.0);
+        let adjusted = safe_velocity(&position, &head_on, &neighbors, 2.0, 5.0);
+        assert!(adjusted.distance_to(&head_on) > 0.1);
+        
+        // The adjusted path clears the neighbor by the combined radius
+        let toward = Vector3D::new(1.0, 0.0, 0.0);
+        let angle = (adjusted.dot(&toward) / adjusted.magnitude()).acos();
+        assert!(angle >= (2.0f64 / 10.0).asin() - 1e-9);
+        
+        let sideways = Vector3D::new(0.0, 5.0, 0.0);
+        let unchanged = safe_velocity(&position, &sideways, &neighbors, 2.0, 5.0);
+        assert_eq!(unchanged.distance_to(&sideways), 0.0);
+    }
+    
+    #[test]
+    fn test_heading_to() {
+        let from = Vector3D::new(10.0, 10.0, 5.0);
+        let (yaw, pitch) = heading_to(&from, &Vector3D::new(10.0, 60.0, 5.0));
+        assert!((yaw - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert!(pitch.abs() < 1e-12);
+        
+        let (_, pitch) = heading_to(&from, &Vector3D::new(10.0, 10.0, 40.0));
+        assert!((pitch - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_is_hovering() {
+        let gravity = 9.81;
+        let thrust = required_thrust(&Vector3D::zero(), gravity, 1.0);
+        let net = thrust.subtract(&Vector3D::new(0.0, 0.0, gravity));
+        
+        assert!(is_hovering(&Vector3D::new(0.02, -0.01, 0.0), 0.1, &net, 0.05));
+        assert!(!is_hovering(&Vector3D::new(3.0, 0.0, 0.0), 0.1, &net, 0.05));
+        assert!(!is_hovering(&Vector3D::zero(), 0.1, &Vector3D::new(0.0, 0.0, -1.0), 0.05));
+    }
+    
+    #[test]
+    fn test_required_thrust_hover() {
+        let thrust = required_thrust(&Vector3D::zero(), 9.81, 1.5);
+        assert_eq!(thrust.x, 0.0);
+        assert_eq!(thrust.y, 0.0);
+        assert!((thrust.z - 1.5 * 9.81).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_reflect_off_x_wall() {
+        let velocity = Vector3D::new(3.0, -2.0, 1.0);
+        let reflected = velocity.reflect(&Vector3D::new(-2.0, 0.0, 0.0));
+        assert_eq!((reflected.x, reflected.y, reflected.z), (-3.0, -2.0, 1.0));
+        
+        let bounds = BoundingBox::new(Vector3D::new(0.0, 0.0, 0.0), Vector3D::new(10.0, 10.0, 10.0));
+        let (position, bounced) = bounds.bounce(&Vector3D::new(10.5, 5.0, 5.0), &velocity);
+        assert_eq!((bounced.x, bounced.y, bounced.z), (-3.0, -2.0, 1.0));
+        assert!((position.x - 9.5).abs() < 1e-12);
+        assert_eq!((position.y, position.z), (5.0, 5.0));
+    }
+    
+    #[test]
+    fn test_typed_avoidance_respects_whitelist() {
+        let position = Vector3D::new(0.0, 0.0, 0.0);
+        let velocity = Vector3D::zero();
+        let obstacles = vec![Obstacle {
+            position: (3.0, 0.0, 0.0),
+            size: 0.5,
+            obstacle_type: "small_object".to_string(),
+        }];
+        
+        let params = AvoidanceParams { avoidance_distance: 10.0, ..AvoidanceParams::default() };
+        let all = calculate_typed_obstacle_avoidance(&position, &velocity, &obstacles, &params, &[]);
+        assert!(all.x < 0.0);
+        
+        let buildings_only = vec!["building".to_string()];
+        let filtered = calculate_typed_obstacle_avoidance(
+            &position, &velocity, &obstacles, &params, &buildings_only,
+        );
+        assert_eq!(filtered.magnitude(), 0.0);
+    }
+    
+    #[test]
+    fn test_forward_only_avoidance_ignores_obstacles_behind() {
+        let position = Vector3D::new(0.0, 0.0, 10.0);
+        let velocity = Vector3D::new(5.0, 0.0, 0.0);
+        let behind = [(-8.0, 0.0, 10.0, 2.0)];
+        let ahead = [(8.0, 0.0, 10.0, 2.0)];
+        let forward_only = AvoidanceParams { forward_only: true, ..AvoidanceParams::default() };
+        
+        assert!(calculate_obstacle_avoidance(&position, &velocity, &behind, &AvoidanceParams::default()).x > 0.0);
+        assert_eq!(calculate_obstacle_avoidance(&position, &velocity, &behind, &forward_only).magnitude(), 0.0);
+        assert!(calculate_obstacle_avoidance(&position, &velocity, &ahead, &forward_only).x < 0.0);
+        
+        // Hovering drones have no forward direction and avoid all around
+        let hovering = calculate_obstacle_avoidance(&position, &Vector3D::zero(), &behind, &forward_only);
+        assert!(hovering.x > 0.0);
+    }
+    
+    #[test]
+    fn test_speed_scaled_avoidance_grows_with_speed() {
+        let position = Vector3D::new(0.0, 0.0, 10.0);
+        let obstacles = [(8.0, 0.0, 10.0, 2.0)];
+        let params = FlockingParams::default();
+        let scaled = AvoidanceParams { speed_scaled_avoidance: true, ..AvoidanceParams::default() };
+        let avoid_at = |speed: f64, avoidance: &AvoidanceParams| {
+            let velocity = Vector3D::new(speed, 0.0, 0.0);
+            combine_with_avoidance(&Vector3D::zero(), &position, &velocity, &obstacles, &params, avoidance)
+        };
+        
+        let slow = avoid_at(5.0, &scaled);
+        let fast = avoid_at(40.0, &scaled);
+        assert!(slow.x < 0.0);
+        assert!(fast.magnitude() > slow.magnitude());
+        
+        // Capped once the drone reaches max_speed
+        let full = avoid_at(params.max_speed, &scaled).magnitude();
+        assert!((avoid_at(params.max_speed * 3.0, &scaled).magnitude() - full).abs() < 1e-12);
+        
+        // Off by default: speed makes no difference
+        let unscaled = AvoidanceParams::default();
+        assert_eq!(avoid_at(40.0, &unscaled).magnitude(), avoid_at(5.0, &unscaled).magnitude());
+        assert!((avoid_at(5.0, &unscaled).magnitude() - full).abs() < 1e-12);
+        
+        // A hovering drone in critical range still gets the full override
+        let critical = AvoidanceParams { avoidance_priority: true, critical_distance: 10.0, ..scaled };
+        let flocking = Vector3D::new(3.0, 0.0, 0.0);
+        let hover = combine_with_avoidance(&flocking, &position, &Vector3D::zero(), &obstacles, &params, &critical);
+        assert!(hover.x < 0.0);
+        assert!((hover.magnitude() - full).abs() < 1e-12);
+    }
+    
+    #[test]
+    fn test_head_on_approach_steers_sideways() {
+        let position = Vector3D::new(0.0, 0.0, 10.0);
+        let velocity = Vector3D::new(5.0, 0.0, 0.0);
+        let obstacles = vec![(10.0, 0.0, 10.0, 2.0)];
+        
+        let repulsion_only = calculate_obstacle_avoidance(&position, &velocity, &obstacles, &AvoidanceParams::default());
+        assert!(repulsion_only.x < 0.0);
+        assert_eq!(repulsion_only.y, 0.0);
+        
+        let params = AvoidanceParams { tangential_weight: 1.0, ..AvoidanceParams::default() };
+        let steering = calculate_obstacle_avoidance(&position, &velocity, &obstacles, &params);
+        let sideways = (steering.y * steering.y + steering.z * steering.z).sqrt();
+        assert!(sideways > 0.1);
+    }
+    
+    #[test]
+    fn test_swarm_frame_centers_and_aligns() {
+        let make = |id: &str, x: f64, y: f64, vx: f64, vy: f64| drone(id, (x, y, 10.0), (vx, vy, 0.0));
+        let states = vec![
+            make("a", 10.0, 20.0, 0.0, 5.0),
+            make("b", 14.0, 22.0, 1.0, 4.0),
+            make("c", 12.0, 27.0, -1.0, 6.0),
+        ];
+        
+        let frame = to_swarm_frame(&states);
+        
+        let mut centroid = Vector3D::zero();
+        let mut heading = Vector3D::zero();
+        for (position, velocity) in &frame {
+            centroid = centroid.add(position);
+            heading = heading.add(velocity);
+        }
+        
+        assert!(centroid.magnitude() < 1e-9);
+        assert!(heading.x > 0.0);
+        assert!(heading.y.abs() < 1e-9);
+        
+        // Climbing, purely vertical and reversed swarms are turned onto +x too
+        for climb in [3.0, 50.0] {
+            let climbing: Vec<DroneState> = states
+                .iter()
+                .map(|s| DroneState {
+                    velocity: DroneVelocity { vz: climb, ..s.velocity.clone() },
+                    ..s.clone()
+                })
+                .collect();
+            let vertical: Vec<DroneState> = states
+                .iter()
+                .map(|s| DroneState { velocity: DroneVelocity { vx: 0.0, vy: 0.0, vz: climb }, ..s.clone() })
+                .collect();
+            let reversed: Vec<DroneState> = states
+                .iter()
+                .map(|s| DroneState { velocity: DroneVelocity { vx: -climb, vy: 0.0, vz: 0.0 }, ..s.clone() })
+                .collect();
+            for swarm in [climbing, vertical, reversed] {
+                let heading = to_swarm_frame(&swarm)
+                    .iter()
+                    .fold(Vector3D::zero(), |sum, (_, velocity)| sum.add(velocity));
+                assert!(heading.x > 0.0);
+                assert!(heading.y.abs() < 1e-9);
+                assert!(heading.z.abs() < 1e-9);
+            }
+        }
     }
 }
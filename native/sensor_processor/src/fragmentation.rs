@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use crate::smoothing::Warmup;
+
+/// Moving fragmentation index over the swarm's last `window` frames, fed
+/// with the number of connected components observed each frame.
+pub struct FragmentationMonitor {
+    window: usize,
+    counts: Mutex<VecDeque<usize>>,
+    warmup: Warmup,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for FragmentationMonitor {}
+
+impl FragmentationMonitor {
+    pub fn new(window: usize) -> Self {
+        Self::with_warmup(window, 0)
+    }
+    
+    /// Like `new`, but not warm until `warmup_frames` frames have been
+    /// pushed. Setting it to `window` waits for a full window.
+    pub fn with_warmup(window: usize, warmup_frames: usize) -> Self {
+        Self {
+            window: window.max(1),
+            counts: Mutex::new(VecDeque::new()),
+            warmup: Warmup::new(warmup_frames),
+        }
+    }
+    
+    pub fn is_warm(&self) -> bool {
+        self.warmup.is_warm()
+    }
+    
+    /// Records one frame's component count and returns the fragmentation
+    /// index with whether this frame split the swarm further than the last.
+    ///
+    /// Each frame contributes `1 - 1 / components` (0 for a single group,
+    /// approaching 1 as it shatters) and the index is the mean over the
+    /// window. An empty swarm (zero components) counts as unfragmented.
+    pub fn push(&self, components: usize) -> (f64, bool) {
+        let mut counts = self.counts.lock().unwrap();
+        self.warmup.tick();
+        let split = counts.back().is_some_and(|&previous| components > previous);
+        
+        counts.push_back(components);
+        if counts.len() > self.window {
+            counts.pop_front();
+        }
+        
+        let index = counts
+            .iter()
+            .map(|&count| if count > 1 { 1.0 - 1.0 / count as f64 } else { 0.0 })
+            .sum::<f64>()
+            / counts.len() as f64;
+        (index, split)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_split_is_flagged() {
+        let monitor = FragmentationMonitor::new(4);
+        for _ in 0..3 {
+            assert_eq!(monitor.push(1), (0.0, false));
+        }
+        
+        let (index, split) = monitor.push(2);
+        assert!(split);
+        assert!((index - 0.125).abs() < 1e-12);
+        
+        // Staying split is not a new event, but the index keeps rising
+        let (steady, split) = monitor.push(2);
+        assert!(!split);
+        assert!(steady > index);
+        
+        assert!(!monitor.push(1).1);
+    }
+    
+    #[test]
+    fn test_warm_after_full_window() {
+        let monitor = FragmentationMonitor::with_warmup(4, 4);
+        for _ in 0..4 {
+            assert!(!monitor.is_warm());
+            monitor.push(1);
+        }
+        assert!(monitor.is_warm());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use crate::flocking::Vector3D;
+use crate::{DronePosition, DroneState, DroneVelocity};
+
+/// Recent timestamped positions per drone, for feeds that report positions
+/// but not velocities.
+pub struct StateHistory {
+    capacity: usize,
+    samples: Mutex<HashMap<String, VecDeque<(DronePosition, u64)>>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for StateHistory {}
+
+impl StateHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(2),
+            samples: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Records a position and returns the drone's state with velocity
+    /// estimated by finite difference against the previous sample. The
+    /// first sample, or one with no time elapsed, reports zero velocity.
+    pub fn push(&self, id: String, position: DronePosition, timestamp: u64) -> DroneState {
+        let mut samples = self.samples.lock().unwrap();
+        let history = samples.entry(id.clone()).or_default();
+        
+        let velocity = match history.back() {
+            Some((previous, previous_time)) if timestamp > *previous_time => {
+                let dt = (timestamp - previous_time) as f64 / 1000.0;
+                DroneVelocity {
+                    vx: (position.x - previous.x) / dt,
+                    vy: (position.y - previous.y) / dt,
+                    vz: (position.z - previous.z) / dt,
+                }
+            }
+            _ => DroneVelocity { vx: 0.0, vy: 0.0, vz: 0.0 },
+        };
+        
+        history.push_back((position.clone(), timestamp));
+        if history.len() > self.capacity {
+            history.pop_front();
+        }
+        
+        DroneState { id, position, velocity, timestamp, weight_overrides: None, priority: 0 }
+    }
+    
+    /// Stored positions for `id`, oldest first.
+    pub fn positions(&self, id: &str) -> Vec<(DronePosition, u64)> {
+        self.samples
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+    
+    /// The last `length` stored positions for `id`, oldest first.
+    pub fn trail(&self, id: &str, length: usize) -> Vec<Vector3D> {
+        let positions = self.positions(id);
+        let skip = positions.len().saturating_sub(length);
+        positions
+            .into_iter()
+            .skip(skip)
+            .map(|(position, _)| Vector3D::from(position))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_finite_difference_velocity() {
+        let history = StateHistory::new(3);
+        let first = history.push("a".to_string(), DronePosition { x: 0.0, y: 0.0, z: 10.0 }, 1_000);
+        assert_eq!(first.velocity.vx, 0.0);
+        
+        let second = history.push("a".to_string(), DronePosition { x: 5.0, y: -2.0, z: 10.0 }, 1_500);
+        assert_eq!((second.velocity.vx, second.velocity.vy, second.velocity.vz), (10.0, -4.0, 0.0));
+        
+        for t in [2_000, 2_500] {
+            history.push("a".to_string(), DronePosition { x: 5.0, y: -2.0, z: 10.0 }, t);
+        }
+        assert_eq!(history.positions("a").len(), 3);
+        assert!(history.positions("b").is_empty());
+    }
+    
+    #[test]
+    fn test_trail_behind_pushes_forward() {
+        use crate::flocking::{trail_repulsion, FlockingParams};
+        
+        let history = StateHistory::new(10);
+        for (i, x) in [-30.0, -20.0, -10.0, 0.0].iter().enumerate() {
+            history.push("a".to_string(), DronePosition { x: *x, y: 0.0, z: 10.0 }, i as u64 * 1_000);
+        }
+        let trail = history.trail("a", 3);
+        assert_eq!(trail.len(), 3);
+        
+        let params = FlockingParams { trail_weight: 0.2, ..FlockingParams::default() };
+        let force = trail_repulsion(&Vector3D::new(0.0, 0.0, 10.0), &trail, &params);
+        assert!(force.x > 0.0);
+        assert!(force.y.abs() < 1e-12 && force.z.abs() < 1e-12);
+        assert!(force.magnitude() <= 0.2 + 1e-12);
+        
+        let off = trail_repulsion(&Vector3D::new(0.0, 0.0, 10.0), &trail, &FlockingParams::default());
+        assert_eq!(off.magnitude(), 0.0);
+    }
+}
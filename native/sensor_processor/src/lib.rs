@@ -1,12 +1,28 @@
-use rustler::{NifResult, NifStruct};
+use rustler::{Atom, Binary, Decoder, NifResult, NifStruct, ResourceArc, Term};
 use serde::{Deserialize, Serialize};
 
 mod sensors;
 mod flocking;
 mod utils;
+mod world;
+mod smoothing;
+mod history;
+mod fragmentation;
+#[cfg(feature = "units")]
+mod units;
 
 use sensors::*;
 use flocking::*;
+use world::WorldState;
+use smoothing::{RadarSmoother, SteeringSmoother};
+use history::StateHistory;
+use fragmentation::FragmentationMonitor;
+
+mod atoms {
+    rustler::atoms! {
+        ok,
+    }
+}
 
 rustler::init!("Elixir.SensorProcessor");
 
@@ -28,14 +44,77 @@ pub struct DroneVelocity {
 
 #[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
 #[module = "DroneState"]
+#[rustler(encode)]
 pub struct DroneState {
     pub id: String,
     pub position: DronePosition,
     pub velocity: DroneVelocity,
     pub timestamp: u64,
+    /// Replaces the swarm-wide rule weights for this drone when set.
+    pub weight_overrides: Option<FlockingWeights>,
+    /// Right-of-way rank. With `priority_yield` set, the lower-priority
+    /// drone of a close pair takes more of the separation.
+    pub priority: i32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+mod drone_state_atoms {
+    rustler::atoms! {
+        struct_key = "__struct__",
+        module = "Elixir.DroneState",
+        id,
+        position,
+        velocity,
+        timestamp,
+        weight_overrides,
+        priority,
+    }
+}
+
+// Decoded by hand so callers whose maps predate `weight_overrides` and
+// `priority` still decode, with no overrides and priority 0
+impl<'a> Decoder<'a> for DroneState {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        use drone_state_atoms::*;
+        
+        let struct_module: Atom = term.map_get(struct_key())?.decode()?;
+        if struct_module != module() {
+            return Err(rustler::Error::RaiseAtom("invalid_struct"));
+        }
+        let weight_overrides = match term.map_get(weight_overrides()) {
+            Ok(value) => value.decode()?,
+            Err(_) => None,
+        };
+        let priority = match term.map_get(priority()) {
+            Ok(value) => value.decode()?,
+            Err(_) => 0,
+        };
+        
+        Ok(DroneState {
+            id: term.map_get(id())?.decode()?,
+            position: term.map_get(position())?.decode()?,
+            velocity: term.map_get(velocity())?.decode()?,
+            timestamp: term.map_get(timestamp())?.decode()?,
+            weight_overrides,
+            priority,
+        })
+    }
+}
+
+/// Test drone at `position` moving at `velocity`, with timestamp 0, no
+/// weight overrides and priority 0. Vary other fields with `..drone(...)`.
+#[cfg(test)]
+pub(crate) fn drone(id: &str, position: (f64, f64, f64), velocity: (f64, f64, f64)) -> DroneState {
+    DroneState {
+        id: id.to_string(),
+        position: DronePosition { x: position.0, y: position.1, z: position.2 },
+        velocity: DroneVelocity { vx: velocity.0, vy: velocity.1, vz: velocity.2 },
+        timestamp: 0,
+        weight_overrides: None,
+        priority: 0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "SensorData"]
 pub struct SensorData {
     pub visual: VisualData,
@@ -53,24 +132,87 @@ fn process_visual_data(raw_data: Vec<u8>) -> NifResult<VisualData> {
     Ok(processed)
 }
 
+#[rustler::nif]
+fn process_visual_data_with_params(raw_data: Vec<u8>, params: VisualParams) -> NifResult<VisualData> {
+    match params.required_len() {
+        Some(len) if params.channels > 0 && len <= raw_data.len() => {}
+        _ => return Err(rustler::Error::BadArg),
+    }
+    
+    let processed = sensors::process_visual_spectrum_with_params(&raw_data, &params);
+    Ok(processed)
+}
+
 #[rustler::nif]
 fn process_audio_data(raw_data: Vec<f32>) -> NifResult<AudioData> {
     let processed = sensors::process_audio_spectrum(&raw_data);
     Ok(processed)
 }
 
+#[rustler::nif]
+fn process_audio_data_with_params(raw_data: Vec<f32>, params: AudioParams) -> NifResult<AudioData> {
+    let processed = sensors::process_audio_spectrum_with_params(&raw_data, &params);
+    Ok(processed)
+}
+
+#[rustler::nif]
+fn dominant_frequency(raw_data: Vec<f32>, sample_rate: f32) -> NifResult<f32> {
+    if sample_rate.is_nan() || sample_rate <= 0.0 {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(sensors::dominant_frequency(&raw_data, sample_rate))
+}
+
 #[rustler::nif]
 fn process_radar_data(raw_data: Vec<f32>) -> NifResult<RadarData> {
     let processed = sensors::process_radar_readings(&raw_data);
     Ok(processed)
 }
 
+#[rustler::nif]
+fn process_radar_data_with_params(raw_data: Vec<f32>, params: RadarParams) -> NifResult<RadarData> {
+    let processed = sensors::process_radar_readings_with_params(&raw_data, &params);
+    Ok(processed)
+}
+
 #[rustler::nif]
 fn process_lidar_data(raw_data: Vec<(f32, f32, f32)>) -> NifResult<LidarData> {
     let processed = sensors::process_lidar_pointcloud(&raw_data);
     Ok(processed)
 }
 
+#[rustler::nif]
+fn process_lidar_data_with_params(
+    raw_data: Vec<(f32, f32, f32)>,
+    params: LidarParams
+) -> NifResult<LidarData> {
+    let processed = sensors::process_lidar_pointcloud_with_params(&raw_data, &params);
+    Ok(processed)
+}
+
+#[rustler::nif]
+fn lidar_stats(
+    raw_data: Vec<(f32, f32, f32)>,
+    params: LidarParams
+) -> NifResult<(usize, usize, usize)> {
+    Ok(sensors::lidar_stats(&raw_data, &params))
+}
+
+#[rustler::nif]
+fn process_lidar_data_packed(
+    data: Binary,
+    scale: f32,
+    origin: (f32, f32, f32)
+) -> NifResult<LidarData> {
+    if !data.len().is_multiple_of(PACKED_POINT_SIZE) || scale <= 0.0 {
+        return Err(rustler::Error::BadArg);
+    }
+    
+    let points = sensors::decode_packed_points(data.as_slice(), scale, origin);
+    let processed = sensors::process_lidar_pointcloud(&points);
+    Ok(processed)
+}
+
 #[rustler::nif]
 fn calculate_flocking_forces(
     drone_state: DroneState,
@@ -81,8 +223,673 @@ fn calculate_flocking_forces(
     Ok((force.x, force.y, force.z))
 }
 
+type NeighborForce = (String, (f64, f64, f64));
+
+#[rustler::nif]
+fn per_neighbor_forces(
+    drone: DroneState,
+    neighbors: Vec<DroneState>,
+    params: FlockingParams
+) -> NifResult<Vec<NeighborForce>> {
+    let contributions = flocking::per_neighbor_forces(&drone, &neighbors, &params)
+        .into_iter()
+        .map(|(id, force)| (id, (force.x, force.y, force.z)))
+        .collect();
+    Ok(contributions)
+}
+
+#[rustler::nif]
+fn params_fingerprint(params: FlockingParams) -> NifResult<u64> {
+    Ok(params.fingerprint())
+}
+
+#[rustler::nif]
+fn calculate_flocking_forces_with_energy(
+    drone_state: DroneState,
+    neighbors: Vec<DroneState>,
+    params: FlockingParams,
+    energy: f64,
+    base: Option<(f64, f64, f64)>,
+    return_energy_threshold: f64
+) -> NifResult<(f64, f64, f64)> {
+    let base = base.map(|(x, y, z)| flocking::Vector3D::new(x, y, z));
+    let force = flocking::calculate_forces_with_energy(
+        &drone_state,
+        &neighbors,
+        &params,
+        energy,
+        base.as_ref(),
+        return_energy_threshold,
+    );
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn preset(name: String) -> NifResult<FlockingParams> {
+    FlockingParams::preset(&name).ok_or(rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn validate_flocking_params(params: FlockingParams) -> NifResult<Vec<String>> {
+    Ok(params.validate())
+}
+
+#[rustler::nif]
+fn analyze_stability(params: FlockingParams) -> NifResult<f64> {
+    Ok(params.oscillation_risk())
+}
+
+#[rustler::nif]
+fn calculate_avoidance_forces(
+    drone_state: DroneState,
+    obstacles: Vec<Obstacle>,
+    params: AvoidanceParams,
+    avoid_types: Vec<String>
+) -> NifResult<(f64, f64, f64)> {
+    let position = flocking::Vector3D::from(drone_state.position);
+    let velocity = flocking::Vector3D::from(drone_state.velocity);
+    let force = flocking::calculate_typed_obstacle_avoidance(
+        &position,
+        &velocity,
+        &obstacles,
+        &params,
+        &avoid_types,
+    );
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn curl_force(
+    position: (f64, f64, f64),
+    center: (f64, f64, f64),
+    axis: (f64, f64, f64),
+    strength: f64
+) -> NifResult<(f64, f64, f64)> {
+    let force = flocking::curl_force(
+        &flocking::Vector3D::new(position.0, position.1, position.2),
+        &flocking::Vector3D::new(center.0, center.1, center.2),
+        &flocking::Vector3D::new(axis.0, axis.1, axis.2),
+        strength,
+    );
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn separation_from_pairs(
+    position: DronePosition,
+    neighbor_positions: Vec<((f64, f64, f64), f64)>,
+    params: FlockingParams
+) -> NifResult<(f64, f64, f64)> {
+    let neighbors: Vec<(flocking::Vector3D, f64)> = neighbor_positions
+        .into_iter()
+        .map(|((x, y, z), distance)| (flocking::Vector3D::new(x, y, z), distance))
+        .collect();
+    let force = flocking::separation_from_pairs(&flocking::Vector3D::from(position), &neighbors, &params);
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn calculate_avoidance_forces_with_hysteresis(
+    drone_state: DroneState,
+    obstacles: Vec<(f64, f64, f64, f64)>,
+    params: AvoidanceParams,
+    hysteresis: AvoidanceHysteresis,
+    active: Vec<bool>
+) -> NifResult<((f64, f64, f64), Vec<bool>)> {
+    let ordered = hysteresis.enter_distance.partial_cmp(&hysteresis.exit_distance);
+    if ordered != Some(std::cmp::Ordering::Less) {
+        return Err(rustler::Error::BadArg);
+    }
+    let position = flocking::Vector3D::from(drone_state.position);
+    let velocity = flocking::Vector3D::from(drone_state.velocity);
+    let mut active = active;
+    let force = flocking::calculate_obstacle_avoidance_with_hysteresis(
+        &position,
+        &velocity,
+        &obstacles,
+        &params,
+        &hysteresis,
+        &mut active,
+    );
+    Ok(((force.x, force.y, force.z), active))
+}
+
+#[rustler::nif]
+fn calculate_capsule_avoidance_forces(
+    drone_state: DroneState,
+    capsules: Vec<Capsule>,
+    params: AvoidanceParams
+) -> NifResult<(f64, f64, f64)> {
+    let position = flocking::Vector3D::from(drone_state.position);
+    let velocity = flocking::Vector3D::from(drone_state.velocity);
+    let force = flocking::calculate_capsule_avoidance(&position, &velocity, &capsules, &params);
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn calculate_combined_forces(
+    drone_state: DroneState,
+    neighbors: Vec<DroneState>,
+    obstacles: Vec<(f64, f64, f64, f64)>,
+    params: FlockingParams,
+    avoidance_params: AvoidanceParams
+) -> NifResult<(f64, f64, f64)> {
+    let flocking_force =
+        flocking::calculate_boids_forces_with_obstacles(&drone_state, &neighbors, &obstacles, &params);
+    let drone_params = params.for_drone(&drone_state);
+    let position = flocking::Vector3D::from(drone_state.position.clone());
+    let velocity = flocking::Vector3D::from(drone_state.velocity.clone());
+    let force = flocking::combine_with_avoidance(
+        &flocking_force,
+        &position,
+        &velocity,
+        &obstacles,
+        &drone_params,
+        &avoidance_params,
+    );
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn forward_clearance(state: DroneState, obstacles: Vec<(f64, f64, f64, f64)>, max_range: f64) -> NifResult<f64> {
+    let position = flocking::Vector3D::from(state.position);
+    let velocity = flocking::Vector3D::from(state.velocity);
+    Ok(flocking::forward_clearance(&position, &velocity, &obstacles, max_range))
+}
+
+#[rustler::nif]
+fn lidar_repulsion(
+    points: Vec<(f32, f32, f32)>,
+    avoidance_distance: f64,
+    max_force: f64
+) -> NifResult<(f64, f64, f64)> {
+    let force = flocking::lidar_repulsion(&points, avoidance_distance, max_force);
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn path_clear(
+    from: (f64, f64, f64),
+    to: (f64, f64, f64),
+    obstacles: Vec<(f64, f64, f64, f64)>,
+    clearance: f64
+) -> NifResult<bool> {
+    let from = flocking::Vector3D::new(from.0, from.1, from.2);
+    let to = flocking::Vector3D::new(to.0, to.1, to.2);
+    Ok(flocking::path_clear(&from, &to, &obstacles, clearance))
+}
+
+#[rustler::nif]
+fn calculate_boundary_forces(
+    drone_state: DroneState,
+    bounds_min: (f64, f64, f64),
+    bounds_max: (f64, f64, f64),
+    boundary_margin: f64
+) -> NifResult<(f64, f64, f64)> {
+    let position = flocking::Vector3D::from(drone_state.position);
+    let velocity = flocking::Vector3D::from(drone_state.velocity);
+    let bounds = flocking::BoundingBox::new(
+        flocking::Vector3D::new(bounds_min.0, bounds_min.1, bounds_min.2),
+        flocking::Vector3D::new(bounds_max.0, bounds_max.1, bounds_max.2),
+    );
+    let force = flocking::boundary_force_box(&position, &velocity, &bounds, boundary_margin);
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn to_swarm_frame(states: Vec<DroneState>) -> NifResult<Vec<DronePosition>> {
+    let positions = flocking::to_swarm_frame(&states)
+        .into_iter()
+        .map(|(position, _)| DronePosition::from(position))
+        .collect();
+    Ok(positions)
+}
+
+#[rustler::nif]
+fn distance_matrix(states: Vec<DroneState>) -> NifResult<Vec<Vec<f64>>> {
+    Ok(flocking::distance_matrix(&states))
+}
+
+#[rustler::nif]
+fn nearest_neighbor_distances(states: Vec<DroneState>) -> NifResult<Vec<f64>> {
+    Ok(flocking::nearest_neighbor_distances(&states))
+}
+
+#[rustler::nif]
+fn sensor_overlap(a: DroneState, b: DroneState, sensor_range: f64, fov_deg: f64) -> NifResult<f64> {
+    Ok(flocking::sensor_overlap(&a, &b, sensor_range, fov_deg))
+}
+
+#[rustler::nif]
+fn angular_momentum(states: Vec<DroneState>) -> NifResult<(f64, f64, f64)> {
+    let momentum = flocking::angular_momentum(&states);
+    Ok((momentum.x, momentum.y, momentum.z))
+}
+
+#[rustler::nif]
+fn swarm_hull_2d(states: Vec<DroneState>) -> NifResult<Vec<(f64, f64)>> {
+    Ok(flocking::swarm_hull_2d(&states))
+}
+
+#[rustler::nif]
+fn local_alignment_errors(states: Vec<DroneState>, neighbor_radius: f64) -> NifResult<Vec<(String, f64)>> {
+    Ok(flocking::local_alignment_errors(&states, neighbor_radius))
+}
+
+#[rustler::nif]
+fn weighted_centroid(points: Vec<(f64, f64, f64)>, weights: Vec<f64>) -> NifResult<(f64, f64, f64)> {
+    let points: Vec<flocking::Vector3D> = points
+        .into_iter()
+        .map(|(x, y, z)| flocking::Vector3D::new(x, y, z))
+        .collect();
+    let centroid = flocking::weighted_centroid(&points, &weights).ok_or(rustler::Error::BadArg)?;
+    Ok((centroid.x, centroid.y, centroid.z))
+}
+
+#[rustler::nif]
+fn order_parameter(states: Vec<DroneState>) -> NifResult<f64> {
+    Ok(flocking::order_parameter(&states))
+}
+
+#[rustler::nif]
+fn expansion_rate(states: Vec<DroneState>) -> NifResult<f64> {
+    Ok(flocking::expansion_rate(&states))
+}
+
+#[rustler::nif]
+fn shape_descriptor(states: Vec<DroneState>) -> NifResult<(f64, f64, f64)> {
+    Ok(flocking::shape_descriptor(&states))
+}
+
+#[rustler::nif]
+fn enclosing_sphere(states: Vec<DroneState>) -> NifResult<((f64, f64, f64), f64)> {
+    let (center, radius) = flocking::enclosing_sphere(&states);
+    Ok(((center.x, center.y, center.z), radius))
+}
+
+#[rustler::nif]
+fn predict_trajectory(
+    state: DroneState,
+    acceleration: (f64, f64, f64),
+    dt: f64,
+    steps: usize,
+    max_speed: f64
+) -> NifResult<Vec<(f64, f64, f64)>> {
+    let position = flocking::Vector3D::from(state.position);
+    let velocity = flocking::Vector3D::from(state.velocity);
+    let acceleration = flocking::Vector3D::new(acceleration.0, acceleration.1, acceleration.2);
+    let trajectory = flocking::predict_trajectory(&position, &velocity, &acceleration, dt, steps, max_speed)
+        .into_iter()
+        .map(|point| (point.x, point.y, point.z))
+        .collect();
+    Ok(trajectory)
+}
+
+#[rustler::nif]
+fn min_jerk_trajectory(
+    start: DroneState,
+    goal: (f64, f64, f64),
+    duration: f64,
+    steps: usize
+) -> NifResult<Vec<(f64, f64, f64)>> {
+    if !(duration.is_finite() && duration > 0.0) || steps == 0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let start = flocking::Vector3D::from(start.position);
+    let goal = flocking::Vector3D::new(goal.0, goal.1, goal.2);
+    let trajectory = flocking::min_jerk_trajectory(&start, &goal, duration, steps)
+        .into_iter()
+        .map(|point| (point.x, point.y, point.z))
+        .collect();
+    Ok(trajectory)
+}
+
+#[rustler::nif]
+fn simplify_trajectory(points: Vec<(f64, f64, f64)>, epsilon: f64) -> NifResult<Vec<(f64, f64, f64)>> {
+    if epsilon.is_nan() || epsilon < 0.0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let points: Vec<flocking::Vector3D> = points
+        .into_iter()
+        .map(|(x, y, z)| flocking::Vector3D::new(x, y, z))
+        .collect();
+    let simplified = flocking::simplify_trajectory(&points, epsilon)
+        .into_iter()
+        .map(|point| (point.x, point.y, point.z))
+        .collect();
+    Ok(simplified)
+}
+
+#[rustler::nif]
+fn safe_velocity(
+    state: DroneState,
+    desired: (f64, f64, f64),
+    neighbors: Vec<DroneState>,
+    combined_radius: f64,
+    horizon: f64
+) -> NifResult<(f64, f64, f64)> {
+    let position = flocking::Vector3D::from(state.position);
+    let desired = flocking::Vector3D::new(desired.0, desired.1, desired.2);
+    let velocity = flocking::safe_velocity(&position, &desired, &neighbors, combined_radius, horizon);
+    Ok((velocity.x, velocity.y, velocity.z))
+}
+
+#[rustler::nif]
+fn heading_to(from: DroneState, target: (f64, f64, f64)) -> NifResult<(f64, f64)> {
+    let position = flocking::Vector3D::from(from.position);
+    let target = flocking::Vector3D::new(target.0, target.1, target.2);
+    Ok(flocking::heading_to(&position, &target))
+}
+
+#[rustler::nif]
+fn intercept_point(pursuer: DroneState, target: DroneState, pursuer_speed: f64) -> NifResult<Option<(f64, f64, f64)>> {
+    let meet = flocking::intercept_point(
+        &flocking::Vector3D::from(pursuer.position),
+        &flocking::Vector3D::from(target.position),
+        &flocking::Vector3D::from(target.velocity),
+        pursuer_speed,
+    );
+    Ok(meet.map(|p| (p.x, p.y, p.z)))
+}
+
+#[rustler::nif]
+fn is_hovering(
+    state: DroneState,
+    speed_eps: f64,
+    accel: (f64, f64, f64),
+    accel_eps: f64
+) -> NifResult<bool> {
+    let velocity = flocking::Vector3D::from(state.velocity);
+    let accel = flocking::Vector3D::new(accel.0, accel.1, accel.2);
+    Ok(flocking::is_hovering(&velocity, speed_eps, &accel, accel_eps))
+}
+
+#[rustler::nif]
+fn required_thrust(
+    desired_accel: (f64, f64, f64),
+    gravity: f64,
+    mass: f64
+) -> NifResult<(f64, f64, f64)> {
+    let desired = flocking::Vector3D::new(desired_accel.0, desired_accel.1, desired_accel.2);
+    let thrust = flocking::required_thrust(&desired, gravity, mass);
+    Ok((thrust.x, thrust.y, thrust.z))
+}
+
 #[rustler::nif]
 fn generate_mock_sensor_data(drone_id: String, noise_level: f64) -> NifResult<SensorData> {
     let data = sensors::generate_mock_data(&drone_id, noise_level);
     Ok(data)
 }
+
+#[rustler::nif]
+fn generate_mock_sensor_data_with_params(
+    drone_id: String,
+    noise_level: f64,
+    params: MockParams
+) -> NifResult<SensorData> {
+    if !params.is_valid() {
+        return Err(rustler::Error::BadArg);
+    }
+    let data = sensors::generate_mock_data_with_params(&drone_id, noise_level, &params);
+    Ok(data)
+}
+
+#[rustler::nif]
+fn merge_obstacles(obstacles: Vec<Obstacle>, merge_distance: f32) -> NifResult<Vec<Obstacle>> {
+    Ok(sensors::merge_obstacles(&obstacles, merge_distance))
+}
+
+#[rustler::nif]
+fn sensor_health(data: SensorData) -> NifResult<f64> {
+    Ok(sensors::sensor_health(&data))
+}
+
+#[rustler::nif]
+fn sensor_disagreement(data: SensorData) -> NifResult<f64> {
+    Ok(sensors::sensor_disagreement(&data))
+}
+
+#[rustler::nif]
+fn serialize_sensor_data(data: SensorData) -> NifResult<Vec<u8>> {
+    sensors::encode_sensor_data(&data).map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn deserialize_sensor_data(bytes: Vec<u8>) -> NifResult<SensorData> {
+    sensors::decode_sensor_data(&bytes).map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn is_stale(timestamp: u64, now: u64, max_age_ms: u64) -> NifResult<bool> {
+    Ok(utils::is_stale(timestamp, now, max_age_ms))
+}
+
+#[rustler::nif]
+fn filter_fresh(states: Vec<DroneState>, now: u64, max_age_ms: u64) -> NifResult<Vec<DroneState>> {
+    Ok(utils::filter_fresh(states, now, max_age_ms))
+}
+
+#[rustler::nif]
+fn quantize_vector(v: (f64, f64, f64), step: f64) -> NifResult<(f64, f64, f64)> {
+    let snapped = flocking::Vector3D::new(v.0, v.1, v.2).quantize(step);
+    Ok((snapped.x, snapped.y, snapped.z))
+}
+
+#[rustler::nif]
+fn canonicalize_state(state: DroneState, max_speed: f64, now: u64) -> NifResult<DroneState> {
+    utils::canonicalize_state(state, max_speed, now).ok_or(rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn snapshot_size_bytes(states: Vec<DroneState>) -> NifResult<usize> {
+    utils::snapshot_size_bytes(&states).map_err(|_| rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn interpolate_state(a: DroneState, b: DroneState, t_ms: u64) -> NifResult<DroneState> {
+    if a.id != b.id {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(utils::interpolate_state(&a, &b, t_ms))
+}
+
+#[rustler::nif]
+fn step_swarm(
+    states: Vec<DroneState>,
+    params: FlockingParams,
+    step_params: StepParams,
+    dt: f64
+) -> NifResult<Vec<DroneState>> {
+    Ok(flocking::step_swarm(&states, &params, &step_params, dt))
+}
+
+#[rustler::nif]
+fn enforce_min_spacing(states: Vec<DroneState>, min_distance: f64, iterations: usize) -> NifResult<Vec<DroneState>> {
+    Ok(flocking::enforce_min_spacing(&states, min_distance, iterations))
+}
+
+#[rustler::nif]
+fn spawn_lattice(count: usize, spacing: f64, origin: (f64, f64, f64), layout: String) -> NifResult<Vec<DroneState>> {
+    flocking::spawn_lattice(count, spacing, origin, &layout).ok_or(rustler::Error::BadArg)
+}
+
+#[rustler::nif]
+fn randomize_velocities(states: Vec<DroneState>, max_speed: f64, seed: u64) -> NifResult<Vec<DroneState>> {
+    Ok(flocking::randomize_velocities(&states, max_speed, seed))
+}
+
+#[rustler::nif]
+fn sample_free_points(
+    center: (f64, f64, f64),
+    radius: f64,
+    count: usize,
+    obstacles: Vec<(f64, f64, f64, f64)>,
+    seed: u64
+) -> NifResult<Vec<(f64, f64, f64)>> {
+    if radius.is_nan() || radius < 0.0 {
+        return Err(rustler::Error::BadArg);
+    }
+    let center = flocking::Vector3D::new(center.0, center.1, center.2);
+    let points = flocking::sample_free_points(&center, radius, count, &obstacles, seed)
+        .into_iter()
+        .map(|point| (point.x, point.y, point.z))
+        .collect();
+    Ok(points)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn simulate(
+    initial: Vec<DroneState>,
+    params: FlockingParams,
+    obstacles: Vec<(f64, f64, f64, f64)>,
+    bounds: (f64, f64, f64),
+    dt: f64,
+    steps: usize,
+    seed: u64
+) -> NifResult<Vec<DroneState>> {
+    Ok(flocking::simulate(&initial, &params, &obstacles, bounds, dt, steps, seed))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn autotune_weights(
+    sample: Vec<DroneState>,
+    base: FlockingParams,
+    iterations: usize
+) -> NifResult<FlockingParams> {
+    Ok(flocking::autotune_weights(&sample, &base, iterations))
+}
+
+#[rustler::nif]
+fn suggest_neighbor_radius(states: Vec<DroneState>, target_neighbors: usize) -> NifResult<f64> {
+    Ok(flocking::suggest_neighbor_radius(&states, target_neighbors))
+}
+
+#[rustler::nif]
+fn world_new() -> NifResult<ResourceArc<WorldState>> {
+    Ok(ResourceArc::new(WorldState::new()))
+}
+
+#[rustler::nif]
+fn world_upsert(world: ResourceArc<WorldState>, id: String, state: DroneState) -> NifResult<Atom> {
+    world.upsert(id, state);
+    Ok(atoms::ok())
+}
+
+#[rustler::nif]
+fn world_remove(world: ResourceArc<WorldState>, id: String) -> NifResult<bool> {
+    Ok(world.remove(&id))
+}
+
+#[rustler::nif]
+fn world_step(world: ResourceArc<WorldState>, params: FlockingParams, dt: f64) -> NifResult<Vec<DroneState>> {
+    Ok(world.step(&params, dt))
+}
+
+#[rustler::nif]
+fn steering_smoother_new(steering_smoothing: f64) -> NifResult<ResourceArc<SteeringSmoother>> {
+    if !(0.0..1.0).contains(&steering_smoothing) {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(ResourceArc::new(SteeringSmoother::new(steering_smoothing)))
+}
+
+#[rustler::nif]
+fn steering_smoother_new_with_warmup(
+    steering_smoothing: f64,
+    warmup_frames: usize
+) -> NifResult<ResourceArc<SteeringSmoother>> {
+    if !(0.0..1.0).contains(&steering_smoothing) {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(ResourceArc::new(SteeringSmoother::with_warmup(steering_smoothing, warmup_frames)))
+}
+
+#[rustler::nif]
+fn steering_smoother_is_warm(smoother: ResourceArc<SteeringSmoother>) -> NifResult<bool> {
+    Ok(smoother.is_warm())
+}
+
+#[rustler::nif]
+fn steering_smoother_apply(
+    smoother: ResourceArc<SteeringSmoother>,
+    force: (f64, f64, f64)
+) -> NifResult<(f64, f64, f64)> {
+    let smoothed = smoother.apply(&flocking::Vector3D::new(force.0, force.1, force.2));
+    Ok((smoothed.x, smoothed.y, smoothed.z))
+}
+
+#[rustler::nif]
+fn radar_smoother_new(alpha: f32) -> NifResult<ResourceArc<RadarSmoother>> {
+    if !(alpha > 0.0 && alpha <= 1.0) {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(ResourceArc::new(RadarSmoother::new(alpha)))
+}
+
+#[rustler::nif]
+fn radar_smoother_new_with_warmup(alpha: f32, warmup_frames: usize) -> NifResult<ResourceArc<RadarSmoother>> {
+    if !(alpha > 0.0 && alpha <= 1.0) {
+        return Err(rustler::Error::BadArg);
+    }
+    Ok(ResourceArc::new(RadarSmoother::with_warmup(alpha, warmup_frames)))
+}
+
+#[rustler::nif]
+fn radar_smoother_is_warm(smoother: ResourceArc<RadarSmoother>) -> NifResult<bool> {
+    Ok(smoother.is_warm())
+}
+
+#[rustler::nif]
+fn radar_smoother_apply(smoother: ResourceArc<RadarSmoother>, range_readings: Vec<f32>) -> NifResult<Vec<f32>> {
+    Ok(smoother.apply(&range_readings))
+}
+
+#[rustler::nif]
+fn state_history_new(capacity: usize) -> NifResult<ResourceArc<StateHistory>> {
+    Ok(ResourceArc::new(StateHistory::new(capacity)))
+}
+
+#[rustler::nif]
+fn state_history_push(
+    history: ResourceArc<StateHistory>,
+    id: String,
+    position: DronePosition,
+    timestamp: u64
+) -> NifResult<DroneState> {
+    Ok(history.push(id, position, timestamp))
+}
+
+#[rustler::nif]
+fn trail_repulsion(
+    history: ResourceArc<StateHistory>,
+    drone: DroneState,
+    params: FlockingParams,
+    trail_length: usize
+) -> NifResult<(f64, f64, f64)> {
+    let trail = history.trail(&drone.id, trail_length);
+    let force = flocking::trail_repulsion(&flocking::Vector3D::from(drone.position), &trail, &params);
+    Ok((force.x, force.y, force.z))
+}
+
+#[rustler::nif]
+fn fragmentation_monitor_new(window: usize) -> NifResult<ResourceArc<FragmentationMonitor>> {
+    Ok(ResourceArc::new(FragmentationMonitor::new(window)))
+}
+
+#[rustler::nif]
+fn fragmentation_monitor_new_with_warmup(
+    window: usize,
+    warmup_frames: usize
+) -> NifResult<ResourceArc<FragmentationMonitor>> {
+    Ok(ResourceArc::new(FragmentationMonitor::with_warmup(window, warmup_frames)))
+}
+
+#[rustler::nif]
+fn fragmentation_monitor_is_warm(monitor: ResourceArc<FragmentationMonitor>) -> NifResult<bool> {
+    Ok(monitor.is_warm())
+}
+
+#[rustler::nif]
+fn fragmentation_monitor_push(monitor: ResourceArc<FragmentationMonitor>, components: usize) -> NifResult<(f64, bool)> {
+    Ok(monitor.push(components))
+}
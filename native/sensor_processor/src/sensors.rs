@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
-use rustler::NifStruct;
+use rustler::{NifStruct, NifUnitEnum};
 use rand::Rng;
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "VisualData"]
 pub struct VisualData {
     pub rgb: (u8, u8, u8),
@@ -13,7 +13,7 @@ pub struct VisualData {
     pub detected_objects: Vec<DetectedObject>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "AudioData"]
 pub struct AudioData {
     pub amplitude: f32,
@@ -22,7 +22,7 @@ pub struct AudioData {
     pub detected_sounds: Vec<SoundSignature>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "RadarData"]
 pub struct RadarData {
     pub range_readings: Vec<f32>,
@@ -30,7 +30,7 @@ pub struct RadarData {
     pub detected_objects: Vec<RadarTarget>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "LidarData"]
 pub struct LidarData {
     pub point_cloud: Vec<(f32, f32, f32)>,
@@ -38,7 +38,7 @@ pub struct LidarData {
     pub detected_obstacles: Vec<Obstacle>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "DetectedObject"]
 pub struct DetectedObject {
     pub object_type: String,
@@ -47,7 +47,7 @@ pub struct DetectedObject {
     pub distance: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "SoundSignature"]
 pub struct SoundSignature {
     pub sound_type: String,
@@ -56,7 +56,7 @@ pub struct SoundSignature {
     pub direction: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "RadarTarget"]
 pub struct RadarTarget {
     pub distance: f32,
@@ -65,7 +65,7 @@ pub struct RadarTarget {
     pub size: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, NifStruct)]
 #[module = "Obstacle"]
 pub struct Obstacle {
     pub position: (f32, f32, f32),
@@ -73,48 +73,415 @@ pub struct Obstacle {
     pub obstacle_type: String,
 }
 
+/// Image layout for visual processing: `width * height` pixels of
+/// `channels` interleaved bytes. A zero width or height treats the whole
+/// buffer as a single row.
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "VisualParams"]
+pub struct VisualParams {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    /// Byte offsets of red, green and blue within each pixel, e.g.
+    /// `(2, 1, 0)` for BGR. Offsets past the last channel read the last one.
+    pub rgb_channels: (usize, usize, usize),
+    /// Replace simulated random draws with fixed values.
+    pub deterministic: bool,
+    /// Measure contrast as the spread of per-pixel luminance instead of the
+    /// spread of individual byte values.
+    pub luminance_contrast: bool,
+}
+
+impl Default for VisualParams {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            channels: 3,
+            rgb_channels: (0, 1, 2),
+            deterministic: false,
+            luminance_contrast: false,
+        }
+    }
+}
+
+impl VisualParams {
+    /// Number of whole pixels the layout covers in a buffer of `len` bytes.
+    pub fn pixel_count(&self, len: usize) -> usize {
+        let available = len / self.channels.max(1);
+        if self.width == 0 || self.height == 0 {
+            available
+        } else {
+            self.width.saturating_mul(self.height).min(available)
+        }
+    }
+    
+    /// Bytes the layout needs, or None if it doesn't fit in a `usize`.
+    pub fn required_len(&self) -> Option<usize> {
+        self.width.checked_mul(self.height)?.checked_mul(self.channels)
+    }
+}
+
+/// How `process_audio_spectrum` estimates direction of arrival. Samples are
+/// treated as interleaved two-channel audio.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, NifUnitEnum)]
+pub enum DirectionMethod {
+    /// Phase difference between the first sample of each channel.
+    PhaseDiff,
+    /// Balance of signal energy between the channels over the whole buffer.
+    Intensity,
+}
+
+/// How the reported audio `amplitude` is derived from the samples.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, NifUnitEnum)]
+pub enum AmplitudeNorm {
+    /// Mean absolute sample value, in raw signal units.
+    None,
+    /// Mean absolute value divided by the peak, so input gain cancels out.
+    Peak,
+    /// Root mean square of the samples.
+    Rms,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "AudioParams"]
+pub struct AudioParams {
+    pub direction_method: DirectionMethod,
+    /// Minimum amplitude for a sound signature to be reported.
+    pub sound_detection_threshold: f32,
+    /// Replace simulated random draws with fixed values.
+    pub deterministic: bool,
+    pub amplitude_norm: AmplitudeNorm,
+}
+
+impl Default for AudioParams {
+    fn default() -> Self {
+        Self {
+            direction_method: DirectionMethod::PhaseDiff,
+            sound_detection_threshold: 0.5,
+            deterministic: false,
+            amplitude_norm: AmplitudeNorm::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, NifStruct)]
+#[module = "RadarParams"]
+pub struct RadarParams {
+    /// Replace simulated random draws with fixed values.
+    pub deterministic: bool,
+}
+
+// Source of the simulated values in sensor processing. In deterministic
+// mode every draw takes the canonical value given at the call site and
+// every chance succeeds, so output depends only on the input and
+// simulated detections are always present to compare.
+struct SimulatedDraws {
+    rng: Option<rand::rngs::ThreadRng>,
+}
+
+impl SimulatedDraws {
+    fn new(deterministic: bool) -> Self {
+        Self {
+            rng: if deterministic { None } else { Some(rand::thread_rng()) },
+        }
+    }
+    
+    fn value(&mut self, range: std::ops::Range<f32>, canonical: f32) -> f32 {
+        match &mut self.rng {
+            Some(rng) => rng.gen_range(range),
+            None => canonical,
+        }
+    }
+    
+    fn chance(&mut self, probability: f32) -> bool {
+        match &mut self.rng {
+            Some(rng) => rng.gen::<f32>() < probability,
+            None => true,
+        }
+    }
+}
+
+/// Return intensity falloff with distance for LiDAR points.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, NifUnitEnum)]
+pub enum IntensityModel {
+    /// `1 / (d + 1)`
+    InverseLinear,
+    /// `1 / (d + 1)^2`
+    InverseSquare,
+    /// Full intensity regardless of distance.
+    Constant,
+}
+
+impl IntensityModel {
+    pub fn intensity(&self, distance: f32) -> f32 {
+        let intensity = match self {
+            IntensityModel::InverseLinear => 1.0 / (distance + 1.0),
+            IntensityModel::InverseSquare => 1.0 / ((distance + 1.0) * (distance + 1.0)),
+            IntensityModel::Constant => 1.0,
+        };
+        intensity.min(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "LidarParams"]
+pub struct LidarParams {
+    pub min_range: f32,
+    pub max_range: f32,
+    pub cluster_distance: f32,
+    pub intensity_model: IntensityModel,
+    /// Smallest cluster reported as an obstacle.
+    pub min_cluster_points: usize,
+}
+
+impl Default for LidarParams {
+    fn default() -> Self {
+        Self {
+            min_range: 0.5,
+            max_range: 200.0,
+            cluster_distance: 2.0,
+            intensity_model: IntensityModel::InverseLinear,
+            min_cluster_points: 6,
+        }
+    }
+}
+
+/// Distance ranges used when generating mock radar and LiDAR readings.
+#[derive(Debug, Clone, Serialize, Deserialize, NifStruct)]
+#[module = "MockParams"]
+pub struct MockParams {
+    pub radar_min_range: f32,
+    pub radar_max_range: f32,
+    pub lidar_min_range: f32,
+    pub lidar_max_range: f32,
+    /// Drive every sensor's noise from one shared per-frame disturbance
+    /// (e.g. airframe vibration) instead of an independent one per sensor.
+    pub correlated_noise: bool,
+}
+
+impl Default for MockParams {
+    fn default() -> Self {
+        Self {
+            radar_min_range: 0.0,
+            radar_max_range: 2.0,
+            lidar_min_range: 10.0,
+            lidar_max_range: 100.0,
+            correlated_noise: false,
+        }
+    }
+}
+
+impl MockParams {
+    /// Whether both ranges are finite and ordered, so they can be sampled.
+    pub fn is_valid(&self) -> bool {
+        let ordered = |min: f32, max: f32| min.is_finite() && max.is_finite() && min <= max;
+        ordered(self.radar_min_range, self.radar_max_range) && ordered(self.lidar_min_range, self.lidar_max_range)
+    }
+}
+
+// Vertical spread of mock LiDAR points around the sensor plane
+const MOCK_LIDAR_Z_SPREAD: f32 = 5.0;
+
 use crate::SensorData;
 
+/// Encodes a sensor frame as a compact bincode blob for frame logs.
+pub fn encode_sensor_data(data: &SensorData) -> Result<Vec<u8>, bincode::Error> {
+    bincode::serialize(data)
+}
+
+pub fn decode_sensor_data(bytes: &[u8]) -> Result<SensorData, bincode::Error> {
+    bincode::deserialize(bytes)
+}
+
+// Variance below which a sensor's signal counts as a flatline
+const FLATLINE_VARIANCE: f32 = 1e-6;
+
+// Share of each sensor's health score earned by reporting any detections
+const DETECTION_HEALTH_SHARE: f64 = 0.2;
+
+/// Rough 0-1 health of a frame: the mean over the four sensors of whether
+/// the signal varies at all (most of the score) and whether it reports any
+/// detections. An all-zero frame scores zero.
+pub fn sensor_health(data: &SensorData) -> f64 {
+    let lidar_ranges: Vec<f32> = data
+        .lidar
+        .point_cloud
+        .iter()
+        .map(|&(x, y, z)| (x * x + y * y + z * z).sqrt())
+        .collect();
+    
+    let sensors = [
+        (data.visual.contrast > 0.0, !data.visual.detected_objects.is_empty()),
+        (variance(&data.audio.frequency_spectrum) > FLATLINE_VARIANCE, !data.audio.detected_sounds.is_empty()),
+        (variance(&data.radar.range_readings) > FLATLINE_VARIANCE, !data.radar.detected_objects.is_empty()),
+        (variance(&lidar_ranges) > FLATLINE_VARIANCE, !data.lidar.detected_obstacles.is_empty()),
+    ];
+    let total: f64 = sensors
+        .iter()
+        .map(|&(signal, active)| {
+            let signal = if signal { 1.0 - DETECTION_HEALTH_SHARE } else { 0.0 };
+            let active = if active { DETECTION_HEALTH_SHARE } else { 0.0 };
+            signal + active
+        })
+        .sum();
+    total / sensors.len() as f64
+}
+
+// Gap in meters between an object and its nearest counterpart on another
+// sensor at which the two count as fully contradicting each other
+const DISAGREEMENT_RANGE: f32 = 5.0;
+
+/// Rough 0-1 measure of how badly radar targets, lidar obstacles and visual
+/// detections disagree about where things are. Each detection scores the
+/// gap to the nearest matching detection on the other sensors, relative to
+/// `DISAGREEMENT_RANGE`, and the frame scores the mean. Radar and lidar are
+/// matched in the horizontal plane, with radar angles in degrees from +x;
+/// visual detections carry only a distance, so they are matched by range.
+/// A detection nothing else saw scores 1; a frame with no detections 0.
+pub fn sensor_disagreement(data: &SensorData) -> f64 {
+    let radar: Vec<(f32, f32, f32)> = data
+        .radar
+        .detected_objects
+        .iter()
+        .map(|t| {
+            let angle = t.angle.to_radians();
+            (t.distance * angle.cos(), t.distance * angle.sin(), t.size)
+        })
+        .collect();
+    let lidar: Vec<(f32, f32, f32)> = data
+        .lidar
+        .detected_obstacles
+        .iter()
+        .map(|o| (o.position.0, o.position.1, o.size))
+        .collect();
+    
+    // Edge-to-edge gap to the closest of `others`, if there are any
+    let nearest_gap = |point: &(f32, f32, f32), others: &[(f32, f32, f32)]| {
+        others
+            .iter()
+            .map(|other| {
+                let center_gap = ((point.0 - other.0).powi(2) + (point.1 - other.1).powi(2)).sqrt();
+                (center_gap - point.2 - other.2).max(0.0)
+            })
+            .min_by(|a, b| a.total_cmp(b))
+    };
+    let mismatch = |gap: Option<f32>| gap.map_or(1.0, |gap| (gap / DISAGREEMENT_RANGE).min(1.0)) as f64;
+    
+    let ranges: Vec<f32> = radar
+        .iter()
+        .chain(&lidar)
+        .map(|&(x, y, _)| (x * x + y * y).sqrt())
+        .collect();
+    let scores: Vec<f64> = radar
+        .iter()
+        .map(|t| mismatch(nearest_gap(t, &lidar)))
+        .chain(lidar.iter().map(|o| mismatch(nearest_gap(o, &radar))))
+        .chain(data.visual.detected_objects.iter().map(|object| {
+            let gap = ranges
+                .iter()
+                .map(|range| (range - object.distance).abs())
+                .min_by(|a, b| a.total_cmp(b));
+            mismatch(gap)
+        }))
+        .collect();
+    
+    if scores.is_empty() {
+        return 0.0;
+    }
+    scores.iter().sum::<f64>() / scores.len() as f64
+}
+
+fn variance(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    values.iter().map(|&v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+}
+
 pub fn process_visual_spectrum(raw_data: &[u8]) -> VisualData {
+    process_visual_spectrum_with_params(raw_data, &VisualParams::default())
+}
+
+pub fn process_visual_spectrum_with_params(raw_data: &[u8], params: &VisualParams) -> VisualData {
     // Simulate advanced visual processing
-    let mut rng = rand::thread_rng();
+    let mut draws = SimulatedDraws::new(params.deterministic);
+    
+    let channels = params.channels.max(1);
+    let pixel_count = params.pixel_count(raw_data.len());
+    let image = &raw_data[..pixel_count * channels];
+    let pixels = image.chunks_exact(channels);
     
-    // Extract RGB from raw data (simplified)
-    let rgb = if raw_data.len() >= 3 {
-        (raw_data[0], raw_data[1], raw_data[2])
+    // Extract RGB as the mean of each channel; single-channel images are gray
+    let rgb = if pixel_count > 0 {
+        let (r, g, b) = params.rgb_channels;
+        let mut sums = [0.0f64; 3];
+        for pixel in pixels.clone() {
+            for (c, sum) in [r, g, b].into_iter().zip(sums.iter_mut()) {
+                *sum += pixel[c.min(channels - 1)] as f64;
+            }
+        }
+        let mean = |sum: f64| (sum / pixel_count as f64).round().clamp(0.0, 255.0) as u8;
+        (mean(sums[0]), mean(sums[1]), mean(sums[2]))
     } else {
         (128, 128, 128)
     };
     
+    // Too short for a single pixel: nothing to measure
+    if pixel_count == 0 {
+        return VisualData {
+            rgb,
+            infrared: 0.0,
+            uv: 0.0,
+            brightness: 0.0,
+            contrast: 0.0,
+            detected_objects: vec![],
+        };
+    }
+    
     // Simulate infrared and UV processing
-    let infrared = raw_data.iter().map(|&x| x as f32).sum::<f32>() / raw_data.len() as f32 * 0.3;
-    let uv = raw_data.iter().map(|&x| x as f32).sum::<f32>() / raw_data.len() as f32 * 0.1;
+    let infrared = image.iter().map(|&x| x as f32).sum::<f32>() / image.len() as f32 * 0.3;
+    let uv = image.iter().map(|&x| x as f32).sum::<f32>() / image.len() as f32 * 0.1;
     
-    // Calculate brightness and contrast
-    let brightness = raw_data.iter().map(|&x| x as f32).sum::<f32>() / (raw_data.len() as f32 * 255.0);
+    // Calculate brightness and contrast, from byte values or, when asked,
+    // per-pixel luminance
+    let samples: Vec<f32> = if params.luminance_contrast {
+        pixels
+            .map(|pixel| pixel.iter().map(|&x| x as f32).sum::<f32>() / channels as f32)
+            .collect()
+    } else {
+        image.iter().map(|&x| x as f32).collect()
+    };
+    let brightness = samples.iter().sum::<f32>() / (samples.len() as f32 * 255.0);
     let mean = brightness * 255.0;
-    let variance = raw_data.iter()
-        .map(|&x| (x as f32 - mean).powi(2))
-        .sum::<f32>() / raw_data.len() as f32;
+    let variance = samples.iter()
+        .map(|&x| (x - mean).powi(2))
+        .sum::<f32>() / samples.len() as f32;
     let contrast = variance.sqrt() / 255.0;
     
     // Simulate object detection
-    let detected_objects = if rng.gen::<f32>() < 0.3 {
+    let mut detected_objects = if draws.chance(0.3) {
         vec![DetectedObject {
             object_type: "drone".to_string(),
-            confidence: rng.gen_range(0.7..0.95),
+            confidence: draws.value(0.7..0.95, 0.8),
             bounding_box: (
-                rng.gen_range(0.0..0.8),
-                rng.gen_range(0.0..0.8),
-                rng.gen_range(0.1..0.3),
-                rng.gen_range(0.1..0.3),
+                draws.value(0.0..0.8, 0.4),
+                draws.value(0.0..0.8, 0.4),
+                draws.value(0.1..0.3, 0.2),
+                draws.value(0.1..0.3, 0.2),
             ),
-            distance: rng.gen_range(10.0..200.0),
+            distance: draws.value(10.0..200.0, 50.0),
         }]
     } else {
         vec![]
     };
     
+    // Most confident first, nearer first on ties
+    detected_objects.sort_by(|a, b| {
+        b.confidence.total_cmp(&a.confidence).then(a.distance.total_cmp(&b.distance))
+    });
+    
     VisualData {
         rgb,
         infrared,
@@ -126,10 +493,22 @@ pub fn process_visual_spectrum(raw_data: &[u8]) -> VisualData {
 }
 
 pub fn process_audio_spectrum(raw_data: &[f32]) -> AudioData {
-    let mut rng = rand::thread_rng();
+    process_audio_spectrum_with_params(raw_data, &AudioParams::default())
+}
+
+pub fn process_audio_spectrum_with_params(raw_data: &[f32], params: &AudioParams) -> AudioData {
+    let mut draws = SimulatedDraws::new(params.deterministic);
     
     // Calculate amplitude
-    let amplitude = raw_data.iter().map(|x| x.abs()).sum::<f32>() / raw_data.len() as f32;
+    let mean_abs = raw_data.iter().map(|x| x.abs()).sum::<f32>() / raw_data.len() as f32;
+    let amplitude = match params.amplitude_norm {
+        AmplitudeNorm::None => mean_abs,
+        AmplitudeNorm::Peak => {
+            let peak = raw_data.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+            if peak > 0.0 { mean_abs / peak } else { 0.0 }
+        }
+        AmplitudeNorm::Rms => (raw_data.iter().map(|x| x * x).sum::<f32>() / raw_data.len() as f32).sqrt(),
+    };
     
     // Simulate FFT for frequency spectrum (simplified)
     let frequency_spectrum: Vec<f32> = (0..10)
@@ -146,18 +525,13 @@ pub fn process_audio_spectrum(raw_data: &[f32]) -> AudioData {
         })
         .collect();
     
-    // Estimate direction using phase differences (simplified)
-    let direction = if raw_data.len() > 1 {
-        (raw_data[0] - raw_data[1]).atan2(raw_data[0] + raw_data[1])
-    } else {
-        0.0
-    };
+    let direction = estimate_direction(raw_data, params.direction_method);
     
     // Detect sound signatures
-    let detected_sounds = if amplitude > 0.5 {
+    let detected_sounds = if amplitude > params.sound_detection_threshold {
         vec![SoundSignature {
             sound_type: "motor".to_string(),
-            frequency: rng.gen_range(100.0..500.0),
+            frequency: draws.value(100.0..500.0, 300.0),
             amplitude,
             direction,
         }]
@@ -173,8 +547,92 @@ pub fn process_audio_spectrum(raw_data: &[f32]) -> AudioData {
     }
 }
 
+/// Frequency in Hz of the strongest spectral bin, ignoring DC. The samples
+/// are zero-padded to a power of two for the FFT, so the result is
+/// quantized to `sample_rate / padded_len`.
+pub fn dominant_frequency(raw_data: &[f32], sample_rate: f32) -> f32 {
+    if raw_data.len() < 2 {
+        return 0.0;
+    }
+    let len = raw_data.len().next_power_of_two();
+    let mut re: Vec<f64> = raw_data.iter().map(|&x| x as f64).collect();
+    re.resize(len, 0.0);
+    let mut im = vec![0.0; len];
+    fft_in_place(&mut re, &mut im);
+    
+    let peak_bin = (1..=len / 2)
+        .max_by(|&a, &b| {
+            let power = |k: usize| re[k] * re[k] + im[k] * im[k];
+            power(a).total_cmp(&power(b))
+        })
+        .unwrap_or(0);
+    peak_bin as f32 * sample_rate / len as f32
+}
+
+// Iterative radix-2 Cooley-Tukey FFT; the length must be a power of two
+fn fft_in_place(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    
+    let mut size = 2;
+    while size <= n {
+        let angle = -std::f64::consts::TAU / size as f64;
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (w_re, w_im) = ((angle * k as f64).cos(), (angle * k as f64).sin());
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size <<= 1;
+    }
+}
+
+fn estimate_direction(raw_data: &[f32], method: DirectionMethod) -> f32 {
+    if raw_data.len() < 2 {
+        return 0.0;
+    }
+    
+    match method {
+        // Estimate direction using phase differences (simplified)
+        DirectionMethod::PhaseDiff => {
+            (raw_data[0] - raw_data[1]).atan2(raw_data[0] + raw_data[1])
+        }
+        DirectionMethod::Intensity => {
+            let (left, right) = raw_data.chunks_exact(2)
+                .fold((0.0, 0.0), |(left, right), pair| {
+                    (left + pair[0] * pair[0], right + pair[1] * pair[1])
+                });
+            (left - right).atan2(left + right)
+        }
+    }
+}
+
 pub fn process_radar_readings(raw_data: &[f32]) -> RadarData {
-    let mut rng = rand::thread_rng();
+    process_radar_readings_with_params(raw_data, &RadarParams::default())
+}
+
+pub fn process_radar_readings_with_params(raw_data: &[f32], params: &RadarParams) -> RadarData {
+    let mut draws = SimulatedDraws::new(params.deterministic);
     
     // Process range readings (distance measurements)
     let range_readings: Vec<f32> = raw_data.iter()
@@ -190,17 +648,20 @@ pub fn process_radar_readings(raw_data: &[f32]) -> RadarData {
         .collect();
     
     // Detect radar targets
-    let detected_objects: Vec<RadarTarget> = range_readings.iter()
+    let mut detected_objects: Vec<RadarTarget> = range_readings.iter()
         .enumerate()
         .filter(|(_, &distance)| distance < 150.0 && distance > 5.0)
         .map(|(idx, &distance)| RadarTarget {
             distance,
             velocity: velocity_readings.get(idx).copied().unwrap_or(0.0),
             angle: idx as f32 * 45.0, // 8 directions, 45° apart
-            size: rng.gen_range(0.5..3.0),
+            size: draws.value(0.5..3.0, 1.0),
         })
         .collect();
     
+    // Nearest first, by bearing on ties
+    detected_objects.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.angle.total_cmp(&b.angle)));
+    
     RadarData {
         range_readings,
         velocity_readings,
@@ -209,29 +670,59 @@ pub fn process_radar_readings(raw_data: &[f32]) -> RadarData {
 }
 
 pub fn process_lidar_pointcloud(raw_data: &[(f32, f32, f32)]) -> LidarData {
-    let mut rng = rand::thread_rng();
-    
-    // Filter and process point cloud
-    let point_cloud: Vec<(f32, f32, f32)> = raw_data.iter()
-        .filter(|(x, y, z)| {
-            let distance = (x*x + y*y + z*z).sqrt();
-            distance > 0.5 && distance < 200.0 // Filter valid range
-        })
-        .cloned()
-        .collect();
+    process_lidar_pointcloud_with_params(raw_data, &LidarParams::default())
+}
+
+pub fn process_lidar_pointcloud_with_params(
+    raw_data: &[(f32, f32, f32)],
+    params: &LidarParams,
+) -> LidarData {
+    let point_cloud = filter_point_cloud(raw_data, params);
     
     // Calculate intensity values
     let intensity: Vec<f32> = point_cloud.iter()
         .map(|(x, y, z)| {
             let distance = (x*x + y*y + z*z).sqrt();
-            (1.0 / (distance + 1.0)).min(1.0) // Intensity decreases with distance
+            params.intensity_model.intensity(distance)
         })
         .collect();
     
     // Detect obstacles using clustering (simplified)
-    let detected_obstacles: Vec<Obstacle> = cluster_points(&point_cloud)
+    let detected_obstacles = detect_obstacles(cluster_points(&point_cloud, params.cluster_distance), params.min_cluster_points);
+    
+    LidarData {
+        point_cloud,
+        intensity,
+        detected_obstacles,
+    }
+}
+
+/// Cheap summary of a LiDAR scan: (valid points, clusters, obstacles).
+pub fn lidar_stats(raw_data: &[(f32, f32, f32)], params: &LidarParams) -> (usize, usize, usize) {
+    let point_cloud = filter_point_cloud(raw_data, params);
+    let clusters = cluster_points(&point_cloud, params.cluster_distance);
+    let cluster_count = clusters.len();
+    let obstacle_count = detect_obstacles(clusters, params.min_cluster_points).len();
+    
+    (point_cloud.len(), cluster_count, obstacle_count)
+}
+
+fn filter_point_cloud(raw_data: &[(f32, f32, f32)], params: &LidarParams) -> Vec<(f32, f32, f32)> {
+    raw_data.iter()
+        .filter(|(x, y, z)| {
+            let distance = (x*x + y*y + z*z).sqrt();
+            distance > params.min_range && distance < params.max_range // Filter valid range
+        })
+        .cloned()
+        .collect()
+}
+
+/// Obstacles found in the clusters, largest first, then nearest to the
+/// sensor, then by position so equal inputs always give the same order.
+fn detect_obstacles(clusters: Vec<Vec<(f32, f32, f32)>>, min_cluster_points: usize) -> Vec<Obstacle> {
+    let mut obstacles: Vec<Obstacle> = clusters
         .into_iter()
-        .filter(|cluster| cluster.len() > 5) // Minimum points for obstacle
+        .filter(|cluster| cluster.len() >= min_cluster_points)
         .map(|cluster| {
             let center = calculate_cluster_center(&cluster);
             let size = calculate_cluster_size(&cluster, &center);
@@ -244,46 +735,205 @@ pub fn process_lidar_pointcloud(raw_data: &[(f32, f32, f32)]) -> LidarData {
         })
         .collect();
     
-    LidarData {
-        point_cloud,
-        intensity,
-        detected_obstacles,
-    }
+    let origin = (0.0, 0.0, 0.0);
+    obstacles.sort_by(|a, b| {
+        b.size
+            .total_cmp(&a.size)
+            .then(calculate_distance(a.position, origin).total_cmp(&calculate_distance(b.position, origin)))
+            .then(a.position.0.total_cmp(&b.position.0))
+            .then(a.position.1.total_cmp(&b.position.1))
+            .then(a.position.2.total_cmp(&b.position.2))
+    });
+    obstacles
+}
+
+// Packed point clouds store each point as three little-endian i16 offsets
+// from an origin, quantized by `scale` meters per unit.
+pub const PACKED_POINT_SIZE: usize = 6;
+
+pub fn decode_packed_points(
+    data: &[u8],
+    scale: f32,
+    origin: (f32, f32, f32),
+) -> Vec<(f32, f32, f32)> {
+    data.chunks_exact(PACKED_POINT_SIZE)
+        .map(|chunk| {
+            let axis = |i: usize| i16::from_le_bytes([chunk[i], chunk[i + 1]]) as f32 * scale;
+            (origin.0 + axis(0), origin.1 + axis(2), origin.2 + axis(4))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub fn pack_points(points: &[(f32, f32, f32)], scale: f32, origin: (f32, f32, f32)) -> Vec<u8> {
+    let quantize = |value: f32, origin: f32| {
+        ((value - origin) / scale)
+            .round()
+            .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+    };
+
+    points.iter()
+        .flat_map(|&(x, y, z)| {
+            [quantize(x, origin.0), quantize(y, origin.1), quantize(z, origin.2)]
+        })
+        .flat_map(i16::to_le_bytes)
+        .collect()
 }
 
 pub fn generate_mock_data(drone_id: &str, noise_level: f64) -> SensorData {
+    generate_mock_data_with_params(drone_id, noise_level, &MockParams::default())
+}
+
+pub fn generate_mock_data_with_params(
+    _drone_id: &str,
+    noise_level: f64,
+    params: &MockParams,
+) -> SensorData {
     let mut rng = rand::thread_rng();
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis() as u64;
+    let raw = mock_raw_frame(&mut rng, noise_level, params);
+    
+    // Make sure long-range mock points survive the LiDAR range filter
+    let defaults = LidarParams::default();
+    let lidar_params = LidarParams {
+        max_range: defaults.max_range.max(params.lidar_max_range + MOCK_LIDAR_Z_SPREAD),
+        ..defaults
+    };
+    
+    SensorData {
+        visual: process_visual_spectrum(&raw.visual),
+        audio: process_audio_spectrum(&raw.audio),
+        radar: process_radar_readings(&raw.radar),
+        lidar: process_lidar_pointcloud_with_params(&raw.lidar, &lidar_params),
+        timestamp,
+    }
+}
+
+// Unprocessed readings for one mock frame
+struct MockRawFrame {
+    visual: Vec<u8>,
+    audio: Vec<f32>,
+    radar: Vec<f32>,
+    lidar: Vec<(f32, f32, f32)>,
+}
+
+fn mock_raw_frame(rng: &mut impl Rng, noise_level: f64, params: &MockParams) -> MockRawFrame {
+    // Relative noise amplitude per sensor for this frame; noisy ranges are
+    // kept inside the configured bounds
+    let [visual_noise, audio_noise, radar_noise, lidar_noise] =
+        noise_gains(rng, params.correlated_noise).map(|gain| (gain * noise_level) as f32);
     
     // Generate mock raw data
-    let visual_raw: Vec<u8> = (0..100).map(|_| rng.gen()).collect();
-    let audio_raw: Vec<f32> = (0..50).map(|_| rng.gen_range(-1.0..1.0)).collect();
-    let radar_raw: Vec<f32> = (0..16).map(|_| rng.gen_range(0.0..2.0)).collect();
+    let visual_raw: Vec<u8> = (0..100)
+        .map(|_| {
+            let byte = rng.gen::<u8>() as f32;
+            jitter(rng, byte, visual_noise).round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+    let audio_raw: Vec<f32> = (0..50)
+        .map(|_| {
+            let sample = rng.gen_range(-1.0..1.0);
+            jitter(rng, sample, audio_noise)
+        })
+        .collect();
+    let radar_raw: Vec<f32> = (0..16)
+        .map(|_| {
+            let range = rng.gen_range(params.radar_min_range..=params.radar_max_range);
+            jitter(rng, range, radar_noise).clamp(params.radar_min_range, params.radar_max_range)
+        })
+        .collect();
     let lidar_raw: Vec<(f32, f32, f32)> = (0..360).map(|i| {
         let angle = i as f32 * std::f32::consts::PI / 180.0;
-        let distance = rng.gen_range(10.0..100.0);
+        let distance = rng.gen_range(params.lidar_min_range..=params.lidar_max_range);
+        let distance = jitter(rng, distance, lidar_noise).clamp(params.lidar_min_range, params.lidar_max_range);
         (
             distance * angle.cos(),
             distance * angle.sin(),
-            rng.gen_range(-5.0..5.0),
+            rng.gen_range(-MOCK_LIDAR_Z_SPREAD..MOCK_LIDAR_Z_SPREAD),
         )
     }).collect();
     
-    SensorData {
-        visual: process_visual_spectrum(&visual_raw),
-        audio: process_audio_spectrum(&audio_raw),
-        radar: process_radar_readings(&radar_raw),
-        lidar: process_lidar_pointcloud(&lidar_raw),
-        timestamp,
+    MockRawFrame { visual: visual_raw, audio: audio_raw, radar: radar_raw, lidar: lidar_raw }
+}
+
+// Per-sensor noise gains in 0..1 for one frame (visual, audio, radar,
+// LiDAR). Correlated noise shares a single disturbance across all four.
+fn noise_gains(rng: &mut impl Rng, correlated: bool) -> [f64; 4] {
+    if correlated {
+        [rng.gen::<f64>(); 4]
+    } else {
+        [rng.gen(), rng.gen(), rng.gen(), rng.gen()]
     }
 }
 
+// Scales `value` by a random relative error of up to `amplitude`
+fn jitter(rng: &mut impl Rng, value: f32, amplitude: f32) -> f32 {
+    value * (1.0 + amplitude * rng.gen_range(-1.0f32..=1.0))
+}
+
+/// Collapses obstacles reported more than once, e.g. by several drones'
+/// LiDAR. Obstacles whose centers are within `merge_distance` of each other,
+/// directly or through a chain, form one group. Each group becomes a single
+/// obstacle at the mean position with the largest size and that largest
+/// member's type. Groups keep the order of their first member.
+pub fn merge_obstacles(obstacles: &[Obstacle], merge_distance: f32) -> Vec<Obstacle> {
+    // Union-find over obstacle indices
+    let mut parent: Vec<usize> = (0..obstacles.len()).collect();
+    for i in 0..obstacles.len() {
+        for j in i + 1..obstacles.len() {
+            if calculate_distance(obstacles[i].position, obstacles[j].position) <= merge_distance {
+                let (a, b) = (find_root(&mut parent, i), find_root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    
+    let mut groups: Vec<(usize, Vec<&Obstacle>)> = Vec::new();
+    for (i, obstacle) in obstacles.iter().enumerate() {
+        let group = find_root(&mut parent, i);
+        match groups.iter_mut().find(|(id, _)| *id == group) {
+            Some((_, members)) => members.push(obstacle),
+            None => groups.push((group, vec![obstacle])),
+        }
+    }
+    
+    groups
+        .into_iter()
+        .map(|(_, members)| {
+            let n = members.len() as f32;
+            let (sx, sy, sz) = members.iter().fold((0.0, 0.0, 0.0), |(x, y, z), o| {
+                (x + o.position.0, y + o.position.1, z + o.position.2)
+            });
+            let largest = members.iter().max_by(|a, b| a.size.total_cmp(&b.size)).unwrap();
+            Obstacle {
+                position: (sx / n, sy / n, sz / n),
+                size: largest.size,
+                obstacle_type: largest.obstacle_type.clone(),
+            }
+        })
+        .collect()
+}
+
+// Union-find root of `i`, halving the path on the way up
+fn find_root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
 // Helper functions for LiDAR processing
 
-fn cluster_points(points: &[(f32, f32, f32)]) -> Vec<Vec<(f32, f32, f32)>> {
+/// Greedy distance-threshold clustering. Points are first sorted along a
+/// Morton (Z-order) curve, so cluster membership and order do not depend
+/// on the order points arrive in.
+fn cluster_points(points: &[(f32, f32, f32)], cluster_distance: f32) -> Vec<Vec<(f32, f32, f32)>> {
+    let points = sort_by_morton(points);
+    
     // Simplified clustering algorithm
     let mut clusters = Vec::new();
     let mut used = vec![false; points.len()];
@@ -303,7 +953,7 @@ fn cluster_points(points: &[(f32, f32, f32)]) -> Vec<Vec<(f32, f32, f32)>> {
             }
             
             let distance = calculate_distance(point, other_point);
-            if distance < 2.0 { // Clustering threshold
+            if distance < cluster_distance {
                 cluster.push(other_point);
                 used[j] = true;
             }
@@ -315,6 +965,57 @@ fn cluster_points(points: &[(f32, f32, f32)]) -> Vec<Vec<(f32, f32, f32)>> {
     clusters
 }
 
+fn sort_by_morton(points: &[(f32, f32, f32)]) -> Vec<(f32, f32, f32)> {
+    let mut min = (f32::MAX, f32::MAX, f32::MAX);
+    let mut max = (f32::MIN, f32::MIN, f32::MIN);
+    for &(x, y, z) in points {
+        min = (min.0.min(x), min.1.min(y), min.2.min(z));
+        max = (max.0.max(x), max.1.max(y), max.2.max(z));
+    }
+    
+    // Quantize each axis onto 21 bits within the cloud's bounding box
+    let quantize = |value: f32, lo: f32, hi: f32| {
+        let extent = hi - lo;
+        if extent > 0.0 {
+            (((value - lo) / extent) * MORTON_AXIS_MAX as f32) as u64
+        } else {
+            0
+        }
+    };
+    
+    let mut keyed: Vec<(u64, (f32, f32, f32))> = points.iter()
+        .map(|&p| {
+            let code = spread_bits(quantize(p.0, min.0, max.0))
+                | spread_bits(quantize(p.1, min.1, max.1)) << 1
+                | spread_bits(quantize(p.2, min.2, max.2)) << 2;
+            (code, p)
+        })
+        .collect();
+    
+    // Break ties within a Morton cell on the raw coordinates for a total order
+    keyed.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(a.1.0.total_cmp(&b.1.0))
+            .then(a.1.1.total_cmp(&b.1.1))
+            .then(a.1.2.total_cmp(&b.1.2))
+    });
+    
+    keyed.into_iter().map(|(_, p)| p).collect()
+}
+
+const MORTON_AXIS_MAX: u64 = (1 << 21) - 1;
+
+// Spreads the low 21 bits of `v` so there are two zero bits between each
+fn spread_bits(v: u64) -> u64 {
+    let mut x = v & MORTON_AXIS_MAX;
+    x = (x | x << 32) & 0x1f00000000ffff;
+    x = (x | x << 16) & 0x1f0000ff0000ff;
+    x = (x | x << 8) & 0x100f00f00f00f00f;
+    x = (x | x << 4) & 0x10c30c30c30c30c3;
+    x = (x | x << 2) & 0x1249249249249249;
+    x
+}
+
 fn calculate_cluster_center(cluster: &[(f32, f32, f32)]) -> (f32, f32, f32) {
     let sum = cluster.iter().fold((0.0, 0.0, 0.0), |acc, &point| {
         (acc.0 + point.0, acc.1 + point.1, acc.2 + point.2)
@@ -345,3 +1046,458 @@ fn classify_obstacle(size: f32) -> String {
         _ => "building".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_sensor_data_binary_round_trip() {
+        let frame = generate_mock_data("drone_1", 0.1);
+        let bytes = encode_sensor_data(&frame).unwrap();
+        assert_eq!(decode_sensor_data(&bytes).unwrap(), frame);
+        assert!(decode_sensor_data(&bytes[..bytes.len() / 2]).is_err());
+    }
+    
+    #[test]
+    fn test_mock_params_reject_unsampleable_ranges() {
+        assert!(MockParams::default().is_valid());
+        
+        let inverted = MockParams { radar_min_range: 3.0, ..MockParams::default() };
+        let nan = MockParams { radar_max_range: f32::NAN, ..MockParams::default() };
+        let infinite = MockParams { lidar_max_range: f32::INFINITY, ..MockParams::default() };
+        assert!(!inverted.is_valid());
+        assert!(!nan.is_valid());
+        assert!(!infinite.is_valid());
+    }
+    
+    #[test]
+    fn test_mock_lidar_range() {
+        let params = MockParams {
+            lidar_min_range: 200.0,
+            lidar_max_range: 300.0,
+            ..MockParams::default()
+        };
+        let data = generate_mock_data_with_params("drone_1", 0.1, &params);
+        
+        assert_eq!(data.lidar.point_cloud.len(), 360);
+        for (x, y, _) in &data.lidar.point_cloud {
+            let distance = (x * x + y * y).sqrt();
+            assert!((199.9..=300.1).contains(&distance), "distance {}", distance);
+        }
+    }
+    
+    #[test]
+    fn test_packed_point_round_trip() {
+        let scale = 0.01;
+        let origin = (5.0, -3.0, 1.0);
+        let point = (12.345, -7.891, 4.567);
+        
+        let packed = pack_points(&[point], scale, origin);
+        assert_eq!(packed.len(), PACKED_POINT_SIZE);
+        
+        let decoded = decode_packed_points(&packed, scale, origin);
+        assert_eq!(decoded.len(), 1);
+        
+        // Quantization error is at most half a step per axis
+        let bound = scale / 2.0 + 1e-4;
+        assert!((decoded[0].0 - point.0).abs() <= bound);
+        assert!((decoded[0].1 - point.1).abs() <= bound);
+        assert!((decoded[0].2 - point.2).abs() <= bound);
+    }
+    
+    #[test]
+    fn test_lidar_stats_match_full_processing() {
+        let mut raw: Vec<(f32, f32, f32)> = Vec::new();
+        for i in 0..8 {
+            raw.push((20.0 + i as f32 * 0.1, 5.0, 1.0));
+            raw.push((-40.0, 30.0 + i as f32 * 0.2, 0.0));
+        }
+        raw.push((0.1, 0.0, 0.0)); // Too close
+        raw.push((300.0, 0.0, 0.0)); // Too far
+        raw.push((60.0, 60.0, 0.0));
+        
+        let params = LidarParams::default();
+        let (valid, clusters, obstacles) = lidar_stats(&raw, &params);
+        let full = process_lidar_pointcloud_with_params(&raw, &params);
+        
+        assert_eq!(valid, full.point_cloud.len());
+        assert_eq!(obstacles, full.detected_obstacles.len());
+        assert_eq!((valid, clusters, obstacles), (17, 3, 2));
+    }
+    
+    #[test]
+    fn test_inverse_square_intensity_falls_faster() {
+        let raw = vec![(2.0, 0.0, 0.0), (10.0, 0.0, 0.0), (50.0, 0.0, 0.0)];
+        let linear = process_lidar_pointcloud_with_params(&raw, &LidarParams::default());
+        let params = LidarParams { intensity_model: IntensityModel::InverseSquare, ..LidarParams::default() };
+        let square = process_lidar_pointcloud_with_params(&raw, &params);
+        
+        for (square, linear) in square.intensity.iter().zip(&linear.intensity) {
+            assert!(square < linear);
+        }
+        assert!(square.intensity[2] / square.intensity[0] < linear.intensity[2] / linear.intensity[0]);
+    }
+    
+    #[test]
+    fn test_intensity_direction_follows_louder_channel() {
+        let params = AudioParams {
+            direction_method: DirectionMethod::Intensity,
+            ..AudioParams::default()
+        };
+        let loud_left: Vec<f32> = (0..64)
+            .flat_map(|i| {
+                let s = (i as f32 * 0.3).sin();
+                [s, s * 0.1]
+            })
+            .collect();
+        let loud_right: Vec<f32> = loud_left.chunks(2).flat_map(|p| [p[1], p[0]]).collect();
+        
+        assert!(process_audio_spectrum_with_params(&loud_left, &params).direction > 0.0);
+        assert!(process_audio_spectrum_with_params(&loud_right, &params).direction < 0.0);
+    }
+    
+    #[test]
+    fn test_deterministic_sensor_processing() {
+        let image: Vec<u8> = (0..300).map(|i| (i * 7 % 256) as u8).collect();
+        let visual_params = VisualParams { deterministic: true, ..VisualParams::default() };
+        let first = process_visual_spectrum_with_params(&image, &visual_params);
+        assert_eq!(first, process_visual_spectrum_with_params(&image, &visual_params));
+        assert_eq!(first.detected_objects.len(), 1);
+        assert_eq!(first.detected_objects[0].confidence, 0.8);
+        assert_eq!(first.detected_objects[0].bounding_box, (0.4, 0.4, 0.2, 0.2));
+        assert_eq!(first.detected_objects[0].distance, 50.0);
+        
+        let audio: Vec<f32> = (0..40).map(|i| if i % 2 == 0 { 0.9 } else { -0.7 }).collect();
+        let audio_params = AudioParams { deterministic: true, ..AudioParams::default() };
+        let first = process_audio_spectrum_with_params(&audio, &audio_params);
+        assert_eq!(first, process_audio_spectrum_with_params(&audio, &audio_params));
+        assert_eq!(first.detected_sounds[0].frequency, 300.0);
+        
+        let radar = [0.9, 0.2, 1.2, 0.5, 0.0, 0.3, 0.7, 0.1, 1.0, -1.0];
+        let radar_params = RadarParams { deterministic: true };
+        let first = process_radar_readings_with_params(&radar, &radar_params);
+        assert_eq!(first, process_radar_readings_with_params(&radar, &radar_params));
+        assert!(!first.detected_objects.is_empty());
+        assert!(first.detected_objects.iter().all(|target| target.size == 1.0));
+    }
+    
+    #[test]
+    fn test_min_cluster_points() {
+        let points = [(20.0, 0.0, 1.0), (20.5, 0.0, 1.0), (20.0, 0.5, 1.0), (20.5, 0.5, 1.0)];
+        
+        assert!(process_lidar_pointcloud(&points).detected_obstacles.is_empty());
+        let params = LidarParams { min_cluster_points: 3, ..LidarParams::default() };
+        assert_eq!(process_lidar_pointcloud_with_params(&points, &params).detected_obstacles.len(), 1);
+    }
+    
+    #[test]
+    fn test_detection_order_is_deterministic() {
+        // Three blobs of different sizes, fed in scrambled order
+        let mut points = Vec::new();
+        for (cx, spread) in [(20.0f32, 0.3f32), (40.0, 0.8), (60.0, 0.5)] {
+            for i in 0..8 {
+                let offset = (i as f32 - 3.5) * spread / 4.0;
+                points.push((cx + offset, 5.0 + offset, 1.0));
+            }
+        }
+        let mut reversed = points.clone();
+        reversed.reverse();
+        
+        let first = process_lidar_pointcloud(&points).detected_obstacles;
+        let second = process_lidar_pointcloud(&reversed).detected_obstacles;
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, second);
+        assert!(first.windows(2).all(|pair| pair[0].size >= pair[1].size));
+        
+        let radar = process_radar_readings(&[0.9, 0.2, 1.2, 0.5, 0.0, 0.3, 0.7, 0.1]);
+        let angles: Vec<f32> = radar.detected_objects.iter().map(|t| t.angle).collect();
+        assert_eq!(angles, vec![315.0, 45.0, 225.0, 135.0, 270.0, 0.0, 90.0]);
+    }
+    
+    #[test]
+    fn test_solid_red_rgb_any_size_and_layout() {
+        for side in [1usize, 3, 16, 64] {
+            let image: Vec<u8> = (0..side * side).flat_map(|_| [255, 0, 0]).collect();
+            let params = VisualParams { width: side, height: side, ..VisualParams::default() };
+            assert_eq!(process_visual_spectrum_with_params(&image, &params).rgb, (255, 0, 0));
+        }
+        
+        // The same red stored as BGRA
+        let image: Vec<u8> = (0..25).flat_map(|_| [0, 0, 255, 128]).collect();
+        let params = VisualParams {
+            width: 5,
+            height: 5,
+            channels: 4,
+            rgb_channels: (2, 1, 0),
+            ..VisualParams::default()
+        };
+        assert_eq!(process_visual_spectrum_with_params(&image, &params).rgb, (255, 0, 0));
+    }
+    
+    #[test]
+    fn test_checkerboard_mean_rgb_and_contrast() {
+        // 4x4 RGB checkerboard of pure red and pure blue pixels
+        let image: Vec<u8> = (0..16)
+            .flat_map(|i| {
+                let (row, col) = (i / 4, i % 4);
+                if (row + col) % 2 == 0 { [255, 0, 0] } else { [0, 0, 255] }
+            })
+            .collect();
+        let params = VisualParams { width: 4, height: 4, ..VisualParams::default() };
+        
+        let visual = process_visual_spectrum_with_params(&image, &params);
+        assert_eq!(visual.rgb, (128, 0, 128));
+        assert!((visual.brightness - 85.0 / 255.0).abs() < 1e-4);
+        
+        // By default contrast is the spread of byte values, as before;
+        // per-pixel luminance is flat across red and blue squares
+        let byte_contrast = (2.0f32 / 9.0).sqrt();
+        assert!((visual.contrast - byte_contrast).abs() < 1e-4);
+        let luminance = VisualParams { luminance_contrast: true, ..params.clone() };
+        assert!(process_visual_spectrum_with_params(&image, &luminance).contrast < 1e-4);
+        
+        // Black and white squares differ in luminance, so contrast shows up
+        let image: Vec<u8> = (0..16)
+            .flat_map(|i| {
+                let (row, col) = (i / 4, i % 4);
+                if (row + col) % 2 == 0 { [255, 255, 255] } else { [0, 0, 0] }
+            })
+            .collect();
+        let visual = process_visual_spectrum_with_params(&image, &params);
+        assert_eq!(visual.rgb, (128, 128, 128));
+        assert!(visual.contrast > 0.4);
+        assert!(process_visual_spectrum_with_params(&image, &luminance).contrast > 0.4);
+        
+        // Buffers shorter than a pixel measure as empty instead of NaN
+        let tiny = process_visual_spectrum_with_params(&[200, 10], &VisualParams::default());
+        assert_eq!((tiny.brightness, tiny.contrast, tiny.infrared), (0.0, 0.0, 0.0));
+        
+        let huge = VisualParams { width: usize::MAX, height: 2, ..VisualParams::default() };
+        assert_eq!(huge.required_len(), None);
+        assert_eq!(params.required_len(), Some(48));
+    }
+    
+    #[test]
+    fn test_sound_detection_threshold() {
+        let quiet = vec![0.3f32, -0.3, 0.3, -0.3, 0.3, -0.3];
+        
+        let default = process_audio_spectrum_with_params(&quiet, &AudioParams::default());
+        assert!(default.detected_sounds.is_empty());
+        
+        let params = AudioParams { sound_detection_threshold: 0.2, ..AudioParams::default() };
+        let sensitive = process_audio_spectrum_with_params(&quiet, &params);
+        assert_eq!(sensitive.detected_sounds.len(), 1);
+    }
+    
+    #[test]
+    fn test_clustering_is_order_independent() {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+        
+        let mut points: Vec<(f32, f32, f32)> = (0..60)
+            .map(|i| {
+                let base = (i / 10) as f32 * 5.0;
+                (base + (i % 10) as f32 * 0.3, base * 0.5, (i % 3) as f32 * 0.4)
+            })
+            .collect();
+        let expected = cluster_points(&points, 2.0);
+        
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..10 {
+            points.shuffle(&mut rng);
+            assert_eq!(cluster_points(&points, 2.0), expected);
+        }
+    }
+    
+    #[test]
+    fn test_sensor_disagreement_agree_and_contradict() {
+        let frame = |radar_angle: f32| SensorData {
+            visual: VisualData {
+                rgb: (0, 0, 0),
+                infrared: 0.0,
+                uv: 0.0,
+                brightness: 0.0,
+                contrast: 0.0,
+                detected_objects: vec![DetectedObject {
+                    object_type: "building".to_string(),
+                    confidence: 0.9,
+                    bounding_box: (0.0, 0.0, 1.0, 1.0),
+                    distance: 20.0,
+                }],
+            },
+            audio: AudioData {
+                amplitude: 0.0,
+                frequency_spectrum: vec![],
+                direction: 0.0,
+                detected_sounds: vec![],
+            },
+            radar: RadarData {
+                range_readings: vec![20.0],
+                velocity_readings: vec![0.0],
+                detected_objects: vec![RadarTarget { distance: 20.0, velocity: 0.0, angle: radar_angle, size: 1.0 }],
+            },
+            lidar: LidarData {
+                point_cloud: vec![],
+                intensity: vec![],
+                detected_obstacles: vec![Obstacle {
+                    position: (20.5, 0.0, 2.0),
+                    size: 1.0,
+                    obstacle_type: "building".to_string(),
+                }],
+            },
+            timestamp: 0,
+        };
+        
+        // Radar straight ahead matches the lidar obstacle; behind, it doesn't
+        let agree = sensor_disagreement(&frame(0.0));
+        let contradict = sensor_disagreement(&frame(180.0));
+        assert!(agree < 0.1);
+        assert!(contradict > 0.6);
+        
+        let mut empty = frame(0.0);
+        empty.visual.detected_objects.clear();
+        empty.radar.detected_objects.clear();
+        empty.lidar.detected_obstacles.clear();
+        assert_eq!(sensor_disagreement(&empty), 0.0);
+    }
+    
+    #[test]
+    fn test_sensor_health_flatline_and_mock() {
+        let flatline = SensorData {
+            visual: process_visual_spectrum_with_params(
+                &[0; 30],
+                &VisualParams { deterministic: true, ..VisualParams::default() },
+            ),
+            audio: AudioData {
+                amplitude: 0.0,
+                frequency_spectrum: vec![0.0; 10],
+                direction: 0.0,
+                detected_sounds: vec![],
+            },
+            radar: RadarData {
+                range_readings: vec![0.0; 16],
+                velocity_readings: vec![0.0; 16],
+                detected_objects: vec![],
+            },
+            lidar: LidarData {
+                point_cloud: vec![],
+                intensity: vec![],
+                detected_obstacles: vec![],
+            },
+            timestamp: 0,
+        };
+        assert!(sensor_health(&flatline) < 0.1);
+        
+        let mock = generate_mock_data("drone_1", 0.1);
+        assert!(sensor_health(&mock) >= 0.8);
+    }
+    
+    #[test]
+    fn test_correlated_noise_shares_one_disturbance() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        
+        for _ in 0..20 {
+            let gains = noise_gains(&mut rng, true);
+            assert!(gains.iter().all(|&gain| gain == gains[0]));
+        }
+        
+        // Independent gains disagree in at least some frames
+        let independent = (0..20).any(|_| {
+            let gains = noise_gains(&mut rng, false);
+            (gains[0] - gains[1]).abs() > 0.5
+        });
+        assert!(independent);
+    }
+    
+    #[test]
+    fn test_correlated_mock_noise_rises_together_across_sensors() {
+        use rand::SeedableRng;
+        
+        // Mean relative deviation of each frame's visual and audio readings
+        // from the same frame generated without noise
+        let deviations = |correlated_noise: bool| -> Vec<(f64, f64)> {
+            let params = MockParams { correlated_noise, ..MockParams::default() };
+            let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+            (0..40)
+                .map(|_| {
+                    let clean = mock_raw_frame(&mut rng.clone(), 0.0, &params);
+                    let noisy = mock_raw_frame(&mut rng, 0.5, &params);
+                    let visual = clean.visual.iter().zip(&noisy.visual)
+                        .map(|(&c, &n)| (n as f64 - c as f64).abs() / (c as f64).max(1.0))
+                        .sum::<f64>() / clean.visual.len() as f64;
+                    let audio = clean.audio.iter().zip(&noisy.audio)
+                        .map(|(&c, &n)| ((n - c) as f64).abs() / (c.abs() as f64).max(1e-3))
+                        .sum::<f64>() / clean.audio.len() as f64;
+                    (visual, audio)
+                })
+                .collect()
+        };
+        let correlation = |pairs: &[(f64, f64)]| {
+            let n = pairs.len() as f64;
+            let (mean_v, mean_a) = pairs.iter().fold((0.0, 0.0), |(v, a), p| (v + p.0 / n, a + p.1 / n));
+            let cov: f64 = pairs.iter().map(|p| (p.0 - mean_v) * (p.1 - mean_a)).sum();
+            let var_v: f64 = pairs.iter().map(|p| (p.0 - mean_v).powi(2)).sum();
+            let var_a: f64 = pairs.iter().map(|p| (p.1 - mean_a).powi(2)).sum();
+            cov / (var_v * var_a).sqrt()
+        };
+        
+        assert!(correlation(&deviations(true)) > 0.9);
+        assert!(correlation(&deviations(false)) < 0.5);
+    }
+    
+    #[test]
+    fn test_rms_amplitude_of_sine() {
+        // Whole number of periods, so the sampled RMS is exactly A / sqrt(2)
+        let sine: Vec<f32> = (0..400)
+            .map(|i| 0.8 * (i as f32 * std::f32::consts::TAU / 40.0).sin())
+            .collect();
+        let params = |amplitude_norm| AudioParams { amplitude_norm, deterministic: true, ..AudioParams::default() };
+        
+        let rms = process_audio_spectrum_with_params(&sine, &params(AmplitudeNorm::Rms)).amplitude;
+        assert!((rms - 0.8 / 2.0f32.sqrt()).abs() < 1e-4);
+        
+        // Peak normalization removes gain differences
+        let loud: Vec<f32> = sine.iter().map(|x| x * 5.0).collect();
+        let quiet = process_audio_spectrum_with_params(&sine, &params(AmplitudeNorm::Peak)).amplitude;
+        let loud = process_audio_spectrum_with_params(&loud, &params(AmplitudeNorm::Peak)).amplitude;
+        assert!((quiet - loud).abs() < 1e-4);
+    }
+    
+    #[test]
+    fn test_merge_obstacles_collapses_duplicates() {
+        let obstacle = |x: f32, size: f32, obstacle_type: &str| Obstacle {
+            position: (x, 0.0, 5.0),
+            size,
+            obstacle_type: obstacle_type.to_string(),
+        };
+        let reports = vec![
+            obstacle(10.0, 2.0, "small_object"),
+            obstacle(50.0, 1.0, "small_object"),
+            obstacle(11.0, 6.0, "building"),
+        ];
+        
+        let merged = merge_obstacles(&reports, 2.0);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].position, (10.5, 0.0, 5.0));
+        assert_eq!(merged[0].size, 6.0);
+        assert_eq!(merged[0].obstacle_type, "building");
+        assert_eq!(merged[1], reports[1]);
+    }
+    
+    #[test]
+    fn test_dominant_frequency_of_tone() {
+        let sample_rate = 8000.0;
+        let tone: Vec<f32> = (0..1000)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                (std::f32::consts::TAU * 250.0 * t).sin() + 0.2 * (std::f32::consts::TAU * 1800.0 * t).sin()
+            })
+            .collect();
+        
+        let frequency = dominant_frequency(&tone, sample_rate);
+        assert!((frequency - 250.0).abs() <= sample_rate / 1024.0, "frequency {}", frequency);
+        assert_eq!(dominant_frequency(&[0.5], sample_rate), 0.0);
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::flocking::Vector3D;
+
+/// Counts the frames a stateful filter has taken so callers can tell when
+/// its output has converged enough to act on.
+pub(crate) struct Warmup {
+    frames: usize,
+    seen: AtomicUsize,
+}
+
+impl Warmup {
+    pub(crate) fn new(frames: usize) -> Self {
+        Self { frames, seen: AtomicUsize::new(0) }
+    }
+    
+    pub(crate) fn tick(&self) {
+        let _ = self.seen.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |seen| seen.checked_add(1));
+    }
+    
+    pub(crate) fn reset(&self) {
+        self.seen.store(0, Ordering::Relaxed);
+    }
+    
+    /// True once `frames` frames have gone through the filter.
+    pub(crate) fn is_warm(&self) -> bool {
+        self.seen.load(Ordering::Relaxed) >= self.frames
+    }
+}
+
+/// Per-drone exponential moving average over the applied acceleration, so
+/// abrupt changes in the raw boids force turn into a short ramp.
+pub struct SteeringSmoother {
+    /// Weight kept from the previous frame; 0 passes the raw force through.
+    steering_smoothing: f64,
+    previous: Mutex<Vector3D>,
+    warmup: Warmup,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for SteeringSmoother {}
+
+impl SteeringSmoother {
+    pub fn new(steering_smoothing: f64) -> Self {
+        Self::with_warmup(steering_smoothing, 0)
+    }
+    
+    /// Like `new`, but not warm until `warmup_frames` forces have been
+    /// applied.
+    pub fn with_warmup(steering_smoothing: f64, warmup_frames: usize) -> Self {
+        Self {
+            steering_smoothing: steering_smoothing.clamp(0.0, 1.0),
+            previous: Mutex::new(Vector3D::zero()),
+            warmup: Warmup::new(warmup_frames),
+        }
+    }
+    
+    pub fn is_warm(&self) -> bool {
+        self.warmup.is_warm()
+    }
+    
+    pub fn apply(&self, raw: &Vector3D) -> Vector3D {
+        let mut previous = self.previous.lock().unwrap();
+        self.warmup.tick();
+        let smoothed = previous
+            .multiply(self.steering_smoothing)
+            .add(&raw.multiply(1.0 - self.steering_smoothing));
+        *previous = smoothed.clone();
+        smoothed
+    }
+}
+
+/// Per-beam exponential smoothing of radar `range_readings` across frames.
+pub struct RadarSmoother {
+    /// Weight given to the newest reading; 1 passes readings through.
+    alpha: f32,
+    previous: Mutex<Vec<f32>>,
+    warmup: Warmup,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for RadarSmoother {}
+
+impl RadarSmoother {
+    pub fn new(alpha: f32) -> Self {
+        Self::with_warmup(alpha, 0)
+    }
+    
+    /// Like `new`, but not warm until `warmup_frames` frames have been
+    /// smoothed since the filter last (re)started.
+    pub fn with_warmup(alpha: f32, warmup_frames: usize) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            previous: Mutex::new(Vec::new()),
+            warmup: Warmup::new(warmup_frames),
+        }
+    }
+    
+    pub fn is_warm(&self) -> bool {
+        self.warmup.is_warm()
+    }
+    
+    /// Smooths one frame. The first frame, or a frame with a different beam
+    /// count, is taken as-is and restarts the filter.
+    pub fn apply(&self, ranges: &[f32]) -> Vec<f32> {
+        let mut previous = self.previous.lock().unwrap();
+        if previous.len() != ranges.len() {
+            *previous = ranges.to_vec();
+            self.warmup.reset();
+            self.warmup.tick();
+            return previous.clone();
+        }
+        self.warmup.tick();
+        
+        for (smoothed, &range) in previous.iter_mut().zip(ranges) {
+            *smoothed += self.alpha * (range - *smoothed);
+        }
+        previous.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_step_change_ramps_up() {
+        let smoother = SteeringSmoother::new(0.6);
+        let target = Vector3D::new(10.0, 0.0, 0.0);
+        
+        let mut last = 0.0;
+        for _ in 0..5 {
+            let x = smoother.apply(&target).x;
+            assert!(x > last && x < target.x);
+            last = x;
+        }
+        for _ in 0..50 {
+            last = smoother.apply(&target).x;
+        }
+        assert!((last - target.x).abs() < 1e-6);
+        
+        let passthrough = SteeringSmoother::new(0.0);
+        assert_eq!(passthrough.apply(&target).x, target.x);
+    }
+    
+    #[test]
+    fn test_radar_step_converges_at_alpha() {
+        let smoother = RadarSmoother::new(0.25);
+        smoother.apply(&[10.0, 50.0]);
+        
+        // Each frame closes a quarter of the remaining gap on the changed beam
+        let mut gap = 40.0f32;
+        for _ in 0..6 {
+            let ranges = smoother.apply(&[50.0, 50.0]);
+            gap *= 0.75;
+            assert!((50.0 - ranges[0] - gap).abs() < 1e-3);
+            assert_eq!(ranges[1], 50.0);
+        }
+    }
+    
+    #[test]
+    fn test_warm_after_warmup_frames() {
+        let smoother = SteeringSmoother::with_warmup(0.5, 3);
+        for _ in 0..3 {
+            assert!(!smoother.is_warm());
+            smoother.apply(&Vector3D::new(1.0, 0.0, 0.0));
+        }
+        assert!(smoother.is_warm());
+        assert!(SteeringSmoother::new(0.5).is_warm());
+        
+        // A beam-count change restarts the radar filter, and its warmup
+        let radar = RadarSmoother::with_warmup(0.5, 2);
+        radar.apply(&[10.0]);
+        radar.apply(&[10.0]);
+        assert!(radar.is_warm());
+        radar.apply(&[10.0, 20.0]);
+        assert!(!radar.is_warm());
+        radar.apply(&[10.0, 20.0]);
+        assert!(radar.is_warm());
+    }
+}
@@ -0,0 +1,83 @@
+//! Explicit unit wrappers for building drone state at call sites. The
+//! NIF-facing structs keep plain `f64` fields in meters and meters per
+//! second; these types just make the expected unit visible where values
+//! are constructed.
+
+use crate::{DronePosition, DroneVelocity};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Meters(pub f64);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct MetersPerSecond(pub f64);
+
+impl Meters {
+    pub fn from_centimeters(cm: f64) -> Self {
+        Self(cm / 100.0)
+    }
+    
+    pub fn from_millimeters(mm: f64) -> Self {
+        Self(mm / 1000.0)
+    }
+    
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl MetersPerSecond {
+    pub fn from_centimeters_per_second(cm_s: f64) -> Self {
+        Self(cm_s / 100.0)
+    }
+    
+    pub fn from_kilometers_per_hour(km_h: f64) -> Self {
+        Self(km_h / 3.6)
+    }
+    
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl DronePosition {
+    pub fn from_meters(x: Meters, y: Meters, z: Meters) -> Self {
+        Self { x: x.0, y: y.0, z: z.0 }
+    }
+    
+    pub fn in_meters(&self) -> (Meters, Meters, Meters) {
+        (Meters(self.x), Meters(self.y), Meters(self.z))
+    }
+}
+
+impl DroneVelocity {
+    pub fn from_meters_per_second(vx: MetersPerSecond, vy: MetersPerSecond, vz: MetersPerSecond) -> Self {
+        Self { vx: vx.0, vy: vy.0, vz: vz.0 }
+    }
+    
+    pub fn in_meters_per_second(&self) -> (MetersPerSecond, MetersPerSecond, MetersPerSecond) {
+        (MetersPerSecond(self.vx), MetersPerSecond(self.vy), MetersPerSecond(self.vz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_typed_constructors_round_trip() {
+        let position = DronePosition::from_meters(Meters(1.5), Meters(-2.0), Meters::from_centimeters(250.0));
+        assert_eq!(position.z, 2.5);
+        assert_eq!(position.in_meters(), (Meters(1.5), Meters(-2.0), Meters(2.5)));
+        
+        let velocity = DroneVelocity::from_meters_per_second(
+            MetersPerSecond(3.0),
+            MetersPerSecond::from_kilometers_per_hour(36.0),
+            MetersPerSecond::from_centimeters_per_second(-50.0),
+        );
+        let (vx, vy, vz) = velocity.in_meters_per_second();
+        assert_eq!(vx.value(), 3.0);
+        assert!((vy.value() - 10.0).abs() < 1e-12);
+        assert_eq!(vz.value(), -0.5);
+        assert_eq!(Meters::from_centimeters(12.0).value(), 0.12);
+    }
+}
@@ -1,5 +1,9 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::flocking::Vector3D;
+use crate::{DronePosition, DroneState, DroneVelocity};
+
+#[allow(dead_code)]
 pub fn current_timestamp_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -7,11 +11,13 @@ pub fn current_timestamp_ms() -> u64 {
         .as_millis() as u64
 }
 
+#[allow(dead_code)]
 pub fn add_noise(value: f64, noise_level: f64) -> f64 {
     let noise = (rand::random::<f64>() - 0.5) * 2.0 * noise_level * value;
     value + noise
 }
 
+#[allow(dead_code)]
 pub fn clamp(value: f64, min: f64, max: f64) -> f64 {
     if value < min {
         min
@@ -26,7 +32,144 @@ pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
     a + (b - a) * t
 }
 
+#[allow(dead_code)]
 pub fn map_range(value: f64, from_min: f64, from_max: f64, to_min: f64, to_max: f64) -> f64 {
     let normalized = (value - from_min) / (from_max - from_min);
     lerp(to_min, to_max, normalized)
 }
+
+/// True when `timestamp` is more than `max_age_ms` older than `now`.
+/// Timestamps from the future (clock skew) never count as stale.
+pub fn is_stale(timestamp: u64, now: u64, max_age_ms: u64) -> bool {
+    now.saturating_sub(timestamp) > max_age_ms
+}
+
+/// Drops drones whose last state update is older than `max_age_ms`.
+pub fn filter_fresh(states: Vec<DroneState>, now: u64, max_age_ms: u64) -> Vec<DroneState> {
+    states
+        .into_iter()
+        .filter(|state| !is_stale(state.timestamp, now, max_age_ms))
+        .collect()
+}
+
+/// Normalizes a state before it is stored: speed is clamped to `max_speed`
+/// (zero disables the clamp) and a zero timestamp becomes `now`. Returns
+/// None if any position or velocity component is not finite.
+pub fn canonicalize_state(mut state: DroneState, max_speed: f64, now: u64) -> Option<DroneState> {
+    let p = &state.position;
+    let v = &state.velocity;
+    if ![p.x, p.y, p.z, v.vx, v.vy, v.vz].iter().all(|c| c.is_finite()) {
+        return None;
+    }
+    
+    if max_speed > 0.0 {
+        let velocity = Vector3D::from(state.velocity.clone()).limit(max_speed);
+        state.velocity = DroneVelocity::from(velocity);
+    }
+    if state.timestamp == 0 {
+        state.timestamp = now;
+    }
+    
+    Some(state)
+}
+
+/// State at `t_ms`, linearly interpolated between two samples of the same
+/// drone. Times outside the samples clamp to the nearer one; the samples
+/// may be given in either order.
+pub fn interpolate_state(a: &DroneState, b: &DroneState, t_ms: u64) -> DroneState {
+    let (early, late) = if a.timestamp <= b.timestamp { (a, b) } else { (b, a) };
+    let t_ms = t_ms.clamp(early.timestamp, late.timestamp);
+    let span = late.timestamp - early.timestamp;
+    let t = if span > 0 {
+        (t_ms - early.timestamp) as f64 / span as f64
+    } else {
+        0.0
+    };
+    
+    let (p0, p1) = (&early.position, &late.position);
+    let (v0, v1) = (&early.velocity, &late.velocity);
+    DroneState {
+        id: early.id.clone(),
+        position: DronePosition {
+            x: lerp(p0.x, p1.x, t),
+            y: lerp(p0.y, p1.y, t),
+            z: lerp(p0.z, p1.z, t),
+        },
+        velocity: DroneVelocity {
+            vx: lerp(v0.vx, v1.vx, t),
+            vy: lerp(v0.vy, v1.vy, t),
+            vz: lerp(v0.vz, v1.vz, t),
+        },
+        timestamp: t_ms,
+        weight_overrides: early.weight_overrides.clone(),
+        priority: early.priority,
+    }
+}
+
+/// Bytes a bincode-encoded snapshot of `states` would take, computed
+/// without building the encoding.
+pub fn snapshot_size_bytes(states: &[DroneState]) -> Result<usize, bincode::Error> {
+    bincode::serialized_size(states).map(|size| size as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drone;
+    
+    #[test]
+    fn test_filter_fresh_drops_stale_drones() {
+        let make = |id: &str, timestamp: u64| DroneState { timestamp, ..drone(id, (0.0, 0.0, 0.0), (0.0, 0.0, 0.0)) };
+        let states = vec![make("ghost", 1_000), make("fresh", 9_500), make("ahead", 10_200)];
+        
+        let fresh = filter_fresh(states, 10_000, 1_000);
+        let ids: Vec<&str> = fresh.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["fresh", "ahead"]);
+        assert!(!is_stale(9_000, 10_000, 1_000));
+    }
+    
+    #[test]
+    fn test_canonicalize_state() {
+        let state = drone("a", (1.0, 2.0, 3.0), (30.0, 40.0, 0.0));
+        
+        let canonical = canonicalize_state(state.clone(), 10.0, 1234).unwrap();
+        assert_eq!(canonical.timestamp, 1234);
+        assert!((canonical.velocity.vx - 6.0).abs() < 1e-12);
+        assert!((canonical.velocity.vy - 8.0).abs() < 1e-12);
+        
+        let stamped = DroneState { timestamp: 99, ..state.clone() };
+        assert_eq!(canonicalize_state(stamped, 0.0, 1234).unwrap().timestamp, 99);
+        
+        let mut broken = state;
+        broken.position.y = f64::NAN;
+        assert!(canonicalize_state(broken, 10.0, 1234).is_none());
+    }
+    
+    #[test]
+    fn test_interpolate_state_midpoint() {
+        let a = DroneState { timestamp: 1_000, ..drone("a", (0.0, 10.0, 20.0), (1.0, 0.0, 0.0)) };
+        let b = DroneState { timestamp: 2_000, ..drone("a", (10.0, 30.0, 20.0), (3.0, 0.0, 0.0)) };
+        
+        let mid = interpolate_state(&b, &a, 1_500);
+        assert_eq!((mid.position.x, mid.position.y, mid.position.z), (5.0, 20.0, 20.0));
+        assert_eq!(mid.velocity.vx, 2.0);
+        assert_eq!(mid.timestamp, 1_500);
+        
+        let after = interpolate_state(&a, &b, 9_000);
+        assert_eq!((after.position.x, after.timestamp), (10.0, 2_000));
+    }
+    
+    #[test]
+    fn test_snapshot_size_matches_encoding() {
+        let states: Vec<DroneState> = (0..5)
+            .map(|i| DroneState {
+                timestamp: 1_000 + i,
+                ..drone(&format!("drone_{}", i), (i as f64, 0.0, 10.0), (0.0, 1.0, 0.0))
+            })
+            .collect();
+        
+        let encoded = bincode::serialize(&states).unwrap();
+        assert_eq!(snapshot_size_bytes(&states).unwrap(), encoded.len());
+        assert!(snapshot_size_bytes(&states[..2]).unwrap() < encoded.len());
+    }
+}
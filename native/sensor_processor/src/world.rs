@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use crate::DroneState;
+use crate::flocking::{self, FlockingParams, StepParams};
+
+/// Swarm state kept on the Rust side between NIF calls, so Elixir only
+/// sends changed drones instead of the full swarm every tick.
+pub struct WorldState {
+    drones: Mutex<BTreeMap<String, DroneState>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for WorldState {}
+
+impl WorldState {
+    pub fn new() -> Self {
+        Self {
+            drones: Mutex::new(BTreeMap::new()),
+        }
+    }
+    
+    pub fn upsert(&self, id: String, mut state: DroneState) {
+        state.id = id.clone();
+        self.drones.lock().unwrap().insert(id, state);
+    }
+    
+    pub fn remove(&self, id: &str) -> bool {
+        self.drones.lock().unwrap().remove(id).is_some()
+    }
+    
+    /// Steps every stored drone and keeps the result. Drones are returned
+    /// (and flocked) in id order.
+    pub fn step(&self, params: &FlockingParams, dt: f64) -> Vec<DroneState> {
+        let mut drones = self.drones.lock().unwrap();
+        let states: Vec<DroneState> = drones.values().cloned().collect();
+        let stepped = flocking::step_swarm(&states, params, &StepParams::default(), dt);
+        
+        for state in &stepped {
+            drones.insert(state.id.clone(), state.clone());
+        }
+        
+        stepped
+    }
+}
+
+impl Default for WorldState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drone;
+    
+    #[test]
+    fn test_world_step_matches_batch_step() {
+        let swarm = vec![
+            drone("a", (0.0, 0.0, 20.0), (2.0, 1.0, 0.0)),
+            drone("b", (10.0, 5.0, 20.0), (-1.0, 1.0, 0.0)),
+            drone("c", (-8.0, 12.0, 20.0), (0.5, 1.0, 0.0)),
+            drone("d", (30.0, -4.0, 20.0), (3.0, 1.0, 0.0)),
+        ];
+        let params = FlockingParams::default();
+        
+        let world = WorldState::new();
+        for state in &swarm {
+            world.upsert(state.id.clone(), state.clone());
+        }
+        world.upsert("gone".to_string(), drone("gone", (5.0, 5.0, 20.0), (0.0, 1.0, 0.0)));
+        assert!(world.remove("gone"));
+        
+        let mut expected = swarm.clone();
+        for _ in 0..5 {
+            expected = flocking::step_swarm(&expected, &params, &StepParams::default(), 0.1);
+            let stepped = world.step(&params, 0.1);
+            
+            assert_eq!(stepped.len(), expected.len());
+            for (actual, expected) in stepped.iter().zip(&expected) {
+                assert_eq!(actual.id, expected.id);
+                assert_eq!(actual.position.x, expected.position.x);
+                assert_eq!(actual.position.y, expected.position.y);
+                assert_eq!(actual.velocity.vx, expected.velocity.vx);
+                assert_eq!(actual.velocity.vy, expected.velocity.vy);
+            }
+        }
+    }
+}